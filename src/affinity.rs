@@ -0,0 +1,63 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::sync::Once;
+
+use core_affinity::CoreId;
+
+use crate::diagnostics::warning;
+
+/// Cores to pin `workers` scoring threads to for `--pin-threads`: the performance cores
+/// of a hybrid CPU if the kernel tells them apart and there are enough of them for a
+/// worker each, otherwise every core this process may run on. Returns `None` where
+/// affinity isn't supported, so workers stay unpinned.
+pub fn worker_cores(workers: usize) -> Option<Vec<CoreId>> {
+    let cores = core_affinity::get_core_ids().filter(|cores| !cores.is_empty())?;
+    let performance: Vec<CoreId> = match performance_cores() {
+        Some(performance) => cores
+            .iter()
+            .copied()
+            .filter(|core| performance.contains(&core.id))
+            .collect(),
+        None => Vec::new(),
+    };
+    // Stacking several workers on each performance core is slower than letting the
+    // extra ones run on efficiency cores. Also reached when this process isn't allowed
+    // on any of the performance cores.
+    Some(if performance.len() < workers {
+        cores
+    } else {
+        performance
+    })
+}
+
+/// Pins the calling thread to the core of worker `thread`, spreading the workers over
+/// `cores` in turn. A thread that can't be pinned is left to the scheduler.
+pub fn pin_worker(cores: &[CoreId], thread: usize) {
+    static WARNING: Once = Once::new();
+    if !core_affinity::set_for_current(cores[thread % cores.len()]) {
+        WARNING.call_once(|| {
+            warning!("Failed to pin a worker thread to its core, leaving it to the scheduler");
+        });
+    }
+}
+
+/// CPUs Linux lists in `/sys/devices/cpu_core/cpus`, which only exists on hybrid Intel
+/// CPUs, where it holds the P-cores (the E-cores are under `cpu_atom`)
+fn performance_cores() -> Option<BTreeSet<usize>> {
+    let list = fs::read_to_string("/sys/devices/cpu_core/cpus").ok()?;
+    parse_cpu_list(list.trim())
+}
+
+/// Parses a kernel CPU list such as `0-7,16,18-19`
+fn parse_cpu_list(list: &str) -> Option<BTreeSet<usize>> {
+    let mut cpus = BTreeSet::new();
+    for item in list.split(',').filter(|item| !item.is_empty()) {
+        match item.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => {
+                cpus.insert(item.parse().ok()?);
+            }
+        }
+    }
+    Some(cpus)
+}
@@ -0,0 +1,262 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use av_metrics_decoders::{ChromaSampling, Decoder, Frame, Pixel, VideoDetails};
+use png::{BitDepth, BlendOp, ColorType, DisposeOp, Transformations};
+
+/// BT.709 luma coefficients of red and blue, which frames are always encoded with
+const KR: f64 = 0.2126;
+const KB: f64 = 0.0722;
+/// Frames are converted to 16-bit YCbCr, so that 8-bit RGB survives the conversion
+const BIT_DEPTH: usize = 16;
+
+/// Decodes the frames of an animated PNG, composited as a viewer would show them.
+/// A PNG without animation control is a single frame.
+///
+/// Frames are full range BT.709 YCbCr 4:4:4, and alpha is dropped like in image mode.
+/// The time base is the delay of the first frame, as variable frame delays can't be
+/// represented.
+pub struct ApngDecoder {
+    reader: png::Reader<BufReader<File>>,
+    width: usize,
+    height: usize,
+    /// Composited RGBA of the last frame, with 16-bit samples
+    canvas: Vec<[u16; 4]>,
+    /// The canvas before the last frame was drawn, restored by `DisposeOp::Previous`
+    previous: Vec<[u16; 4]>,
+    /// How the last frame is disposed of before the next one is drawn
+    dispose: DisposeOp,
+    /// Region of the canvas the last frame covered, as x and y offset, width and height
+    region: (usize, usize, usize, usize),
+    /// Number of animation frames left to read
+    remaining: u32,
+    /// The default image isn't part of the animation and must be skipped
+    skip_default_image: bool,
+    /// Duration of a frame as a numerator and denominator, in seconds
+    delay: Option<(u64, u64)>,
+}
+
+impl ApngDecoder {
+    pub fn new(path: &Path) -> Result<Self, String> {
+        let mut decoder =
+            png::Decoder::new(BufReader::new(File::open(path).map_err(|e| e.to_string())?));
+        decoder.set_transformations(Transformations::EXPAND);
+        let reader = decoder.read_info().map_err(|e| e.to_string())?;
+
+        let info = reader.info();
+        let (width, height) = (info.width as usize, info.height as usize);
+        let remaining = info.animation_control().map_or(1, |actl| actl.num_frames);
+        let skip_default_image = info.animation_control().is_some() && info.frame_control.is_none();
+        let delay = info.frame_control.map(|fc| frame_delay(&fc));
+        Ok(Self {
+            reader,
+            width,
+            height,
+            canvas: vec![[0; 4]; width * height],
+            previous: vec![[0; 4]; width * height],
+            // The canvas starts out fully transparent black
+            dispose: DisposeOp::Background,
+            region: (0, 0, width, height),
+            remaining,
+            skip_default_image,
+            delay,
+        })
+    }
+
+    /// Number of frames of the animation, as declared by its `acTL` chunk
+    pub fn frame_count(&self) -> usize {
+        self.reader
+            .info()
+            .animation_control()
+            .map_or(1, |actl| actl.num_frames as usize)
+    }
+
+    /// Decodes the next frame and composites it onto the canvas.
+    /// Returns `false` at the end of the animation.
+    fn composite_next_frame(&mut self) -> Result<bool, png::DecodingError> {
+        if self.remaining == 0 {
+            return Ok(false);
+        }
+
+        // The output buffer size of the reader only covers the previous frame,
+        // so the buffer is sized for a frame covering the whole canvas
+        let (color_type, bit_depth) = self.reader.output_color_type();
+        let mut buffer =
+            vec![0; self.width * self.height * color_type.samples() * bytes_per_sample(bit_depth)];
+        if self.skip_default_image {
+            self.reader.next_frame(&mut buffer)?;
+            self.skip_default_image = false;
+        }
+
+        match self.dispose {
+            DisposeOp::None => self.previous.clone_from(&self.canvas),
+            DisposeOp::Background => {
+                self.previous.clone_from(&self.canvas);
+                // Only the region of the last frame is cleared, the rest stays visible
+                let (x_offset, y_offset, width, height) = self.region;
+                for y in y_offset..y_offset + height {
+                    let start = y * self.width + x_offset;
+                    self.canvas[start..start + width].fill([0; 4]);
+                }
+            }
+            DisposeOp::Previous => self.canvas.clone_from(&self.previous),
+        }
+
+        let output = self.reader.next_frame(&mut buffer)?;
+        let (x_offset, y_offset, blend) = match self.reader.info().frame_control() {
+            Some(fc) => {
+                self.dispose = fc.dispose_op;
+                if self.delay.is_none() {
+                    self.delay = Some(frame_delay(fc));
+                }
+                (fc.x_offset as usize, fc.y_offset as usize, fc.blend_op)
+            }
+            None => (0, 0, BlendOp::Source),
+        };
+
+        let (width, height) = (output.width as usize, output.height as usize);
+        self.region = (x_offset, y_offset, width, height);
+        for (y, row) in buffer
+            .chunks_exact(output.line_size)
+            .take(height)
+            .enumerate()
+        {
+            let start = (y_offset + y) * self.width + x_offset;
+            for (pixel, dst) in self.canvas[start..start + width].iter_mut().enumerate() {
+                let src = rgba(row, pixel, output.color_type, output.bit_depth);
+                *dst = match blend {
+                    BlendOp::Source => src,
+                    BlendOp::Over => blend_over(src, *dst),
+                };
+            }
+        }
+
+        self.remaining -= 1;
+        Ok(true)
+    }
+
+    /// Composites the next frame and converts it to YCbCr, or `None` after the last one
+    pub fn read_frame<T: Pixel>(&mut self) -> Result<Option<Frame<T>>, String> {
+        match self.composite_next_frame() {
+            Ok(true) => (),
+            Ok(false) => return Ok(None),
+            Err(e) => return Err(format!("failed to decode APNG frame: {e}")),
+        }
+
+        let mut planes = [
+            Vec::with_capacity(self.canvas.len() * 2),
+            Vec::with_capacity(self.canvas.len() * 2),
+            Vec::with_capacity(self.canvas.len() * 2),
+        ];
+        for &[r, g, b, _] in &self.canvas {
+            for (plane, sample) in planes.iter_mut().zip(ycbcr(r, g, b)) {
+                plane.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+
+        let mut frame: Frame<T> =
+            Frame::new_with_padding(self.width, self.height, ChromaSampling::Cs444, 0);
+        for (dst, src) in frame.planes.iter_mut().zip(&planes) {
+            dst.copy_from_raw_u8(src, self.width * 2, 2);
+        }
+        Ok(Some(frame))
+    }
+}
+
+impl Decoder for ApngDecoder {
+    /// Decoding errors are only reported by [`read_frame`](Self::read_frame)
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        self.read_frame().ok().flatten()
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        BIT_DEPTH
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        let mut details = VideoDetails {
+            width: self.width,
+            height: self.height,
+            bit_depth: BIT_DEPTH,
+            chroma_sampling: ChromaSampling::Cs444,
+            ..VideoDetails::default()
+        };
+        // The time base is the duration of a single frame
+        if let Some((num, den)) = self.delay.filter(|&(num, _)| num > 0) {
+            (details.time_base.num, details.time_base.den) = (num, den);
+        }
+        details
+    }
+}
+
+/// The delay of a frame as a fraction of a second. A zero denominator means 1/100 s.
+fn frame_delay(fc: &png::FrameControl) -> (u64, u64) {
+    let den = match fc.delay_den {
+        0 => 100,
+        den => u64::from(den),
+    };
+    (u64::from(fc.delay_num), den)
+}
+
+fn bytes_per_sample(bit_depth: BitDepth) -> usize {
+    if bit_depth == BitDepth::Sixteen {
+        2
+    } else {
+        1
+    }
+}
+
+/// Reads the pixel at index `pixel` of a decoded row as 16-bit RGBA.
+/// Rows are at least 8-bit, since low bit depths and palettes are expanded.
+fn rgba(row: &[u8], pixel: usize, color_type: ColorType, bit_depth: BitDepth) -> [u16; 4] {
+    let samples = color_type.samples();
+    let bytes = bytes_per_sample(bit_depth);
+    let sample = |i: usize| {
+        let offset = (pixel * samples + i) * bytes;
+        if bytes == 2 {
+            u16::from_be_bytes([row[offset], row[offset + 1]])
+        } else {
+            u16::from(row[offset]) * 257
+        }
+    };
+    match color_type {
+        ColorType::Grayscale => [sample(0), sample(0), sample(0), u16::MAX],
+        ColorType::GrayscaleAlpha => [sample(0), sample(0), sample(0), sample(1)],
+        ColorType::Rgb => [sample(0), sample(1), sample(2), u16::MAX],
+        ColorType::Rgba => [sample(0), sample(1), sample(2), sample(3)],
+        ColorType::Indexed => unreachable!("palettes are expanded to RGB"),
+    }
+}
+
+/// Alpha compositing of `src` over `dst`, as specified for `APNG_BLEND_OP_OVER`
+fn blend_over(src: [u16; 4], dst: [u16; 4]) -> [u16; 4] {
+    let max = f64::from(u16::MAX);
+    let src_alpha = f64::from(src[3]) / max;
+    let dst_alpha = f64::from(dst[3]) / max * (1.0 - src_alpha);
+    let alpha = src_alpha + dst_alpha;
+    if alpha == 0.0 {
+        return [0; 4];
+    }
+    let mut out = [0; 4];
+    for (out, (&src, &dst)) in out.iter_mut().zip(src.iter().zip(&dst)).take(3) {
+        *out = ((f64::from(src) * src_alpha + f64::from(dst) * dst_alpha) / alpha).round() as u16;
+    }
+    out[3] = (alpha * max).round() as u16;
+    out
+}
+
+/// Full range samples of a 16-bit R'G'B' pixel, as Y, Cb and Cr
+fn ycbcr(r: u16, g: u16, b: u16) -> [u16; 3] {
+    let max = f64::from(u16::MAX);
+    let (r, g, b) = (f64::from(r) / max, f64::from(g) / max, f64::from(b) / max);
+    let y = KR * r + (1.0 - KR - KB) * g + KB * b;
+    let cb = (b - y) / (2.0 * (1.0 - KB));
+    let cr = (r - y) / (2.0 * (1.0 - KR));
+    let mid = f64::from(1u32 << (BIT_DEPTH - 1));
+    [
+        (y * max).round() as u16,
+        (mid + cb * max).round().clamp(0.0, max) as u16,
+        (mid + cr * max).round().clamp(0.0, max) as u16,
+    ]
+}
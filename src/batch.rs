@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::error::Ssimu2Error;
+
+/// A single comparison of a `batch` jobs file
+#[derive(Debug)]
+pub struct Job {
+    pub source: String,
+    pub distorted: String,
+}
+
+/// Reads a jobs file, with one job per line: either the source and distorted inputs
+/// separated by a tab, or a JSON object with `source` and `distorted` fields.
+/// Empty lines and lines starting with `#` are skipped.
+pub fn read_jobs(path: &Path) -> Result<Vec<Job>, Ssimu2Error> {
+    let contents = fs::read_to_string(path)?;
+    let mut jobs = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |reason: &str| Ssimu2Error::DecodeError {
+            input: format!("{} line {}", path.display(), number + 1),
+            reason: reason.to_string(),
+        };
+        let job = if line.starts_with('{') {
+            let value: Value = serde_json::from_str(line).map_err(|e| invalid(&e.to_string()))?;
+            let field = |name: &str| {
+                value[name]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| invalid(&format!("missing `{name}` string")))
+            };
+            Job {
+                source: field("source")?,
+                distorted: field("distorted")?,
+            }
+        } else {
+            match line.split('\t').collect::<Vec<_>>()[..] {
+                [source, distorted] => Job {
+                    source: source.to_string(),
+                    distorted: distorted.to_string(),
+                },
+                _ => return Err(invalid("expected `source<TAB>distorted` or a JSON object")),
+            }
+        };
+        jobs.push(job);
+    }
+    Ok(jobs)
+}
@@ -0,0 +1,43 @@
+use std::time::Instant;
+
+use av_metrics_decoders::Decoder;
+
+use crate::decode::VideoInput;
+use crate::error::Ssimu2Error;
+use crate::raw::PipedFormat;
+
+/// Reads every frame of `input` and discards it, then prints how long decoding took.
+/// Stops after `frames` frames if given.
+pub fn decode_bench(
+    input: &str,
+    piped_format: Option<PipedFormat>,
+    frames: Option<usize>,
+) -> Result<(), Ssimu2Error> {
+    // Opening includes indexing, which is timed separately from decoding
+    let start = Instant::now();
+    let mut decoder = VideoInput::open(input, piped_format)?;
+    let open_time = start.elapsed();
+
+    let details = decoder.get_video_details();
+    let start = Instant::now();
+    let mut decoded = 0;
+    while frames.map_or(true, |frames| decoded < frames) && decoder.skip_frames(1) {
+        decoded += 1;
+    }
+    let decode_time = start.elapsed();
+
+    println!(
+        "Input: {}x{}, {}-bit, {:?}",
+        details.width, details.height, details.bit_depth, details.chroma_sampling
+    );
+    println!("Open: {:.3} s", open_time.as_secs_f64());
+    println!(
+        "Decoded {decoded} frames in {:.3} s",
+        decode_time.as_secs_f64()
+    );
+    println!(
+        "Throughput: {:.2} fps",
+        decoded as f64 / decode_time.as_secs_f64().max(f64::EPSILON)
+    );
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use clap::ValueEnum;
+
+/// Legacy metric reported next to SSIMULACRA2 by `--also`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ClassicMetric {
+    /// Peak signal-to-noise ratio over all RGB channels, in dB
+    Psnr,
+    /// Structural similarity of the luma, with the usual 11x11 Gaussian window
+    Ssim,
+}
+
+impl ClassicMetric {
+    pub fn label(self) -> &'static str {
+        match self {
+            ClassicMetric::Psnr => "PSNR",
+            ClassicMetric::Ssim => "SSIM",
+        }
+    }
+
+    /// Computes the metric on gamma-encoded RGB in the range 0 to 1.
+    /// Both images must have the given dimensions.
+    pub fn compute(
+        self,
+        source: &[[f32; 3]],
+        distorted: &[[f32; 3]],
+        width: usize,
+        height: usize,
+    ) -> f64 {
+        match self {
+            ClassicMetric::Psnr => psnr(source, distorted),
+            ClassicMetric::Ssim => ssim(&luma(source), &luma(distorted), width, height),
+        }
+    }
+
+    /// Formats a value of this metric for display
+    pub fn format(self, value: f64) -> String {
+        match self {
+            ClassicMetric::Psnr => format!("{value:.4} dB"),
+            ClassicMetric::Ssim => format!("{value:.8}"),
+        }
+    }
+}
+
+/// Identical images have an infinite PSNR
+fn psnr(source: &[[f32; 3]], distorted: &[[f32; 3]]) -> f64 {
+    let sum: f64 = source
+        .iter()
+        .zip(distorted)
+        .flat_map(|(src, dst)| src.iter().zip(dst))
+        .map(|(&src, &dst)| (f64::from(src) - f64::from(dst)).powi(2))
+        .sum();
+    let mse = sum / (source.len() * 3) as f64;
+    -10.0 * mse.log10()
+}
+
+/// BT.709 luma of gamma-encoded RGB
+fn luma(data: &[[f32; 3]]) -> Vec<f64> {
+    data.iter()
+        .map(|&[r, g, b]| 0.2126 * f64::from(r) + 0.7152 * f64::from(g) + 0.0722 * f64::from(b))
+        .collect()
+}
+
+/// Mean SSIM as defined by Wang et al. 2004, with edge pixels clamped for the window
+fn ssim(source: &[f64], distorted: &[f64], width: usize, height: usize) -> f64 {
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+
+    let product = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).collect::<Vec<_>>();
+    let mu_x = blur(source, width, height);
+    let mu_y = blur(distorted, width, height);
+    let xx = blur(&product(source, source), width, height);
+    let yy = blur(&product(distorted, distorted), width, height);
+    let xy = blur(&product(source, distorted), width, height);
+
+    let sum: f64 = (0..width * height)
+        .map(|i| {
+            let (mx, my) = (mu_x[i], mu_y[i]);
+            let var_x = xx[i] - mx * mx;
+            let var_y = yy[i] - my * my;
+            let cov = xy[i] - mx * my;
+            ((2.0 * mx * my + C1) * (2.0 * cov + C2))
+                / ((mx * mx + my * my + C1) * (var_x + var_y + C2))
+        })
+        .sum();
+    sum / (width * height) as f64
+}
+
+/// Separable Gaussian blur with a standard deviation of 1.5 and a radius of 5
+fn blur(plane: &[f64], width: usize, height: usize) -> Vec<f64> {
+    const RADIUS: isize = 5;
+    const SIGMA: f64 = 1.5;
+
+    let weights: Vec<f64> = (-RADIUS..=RADIUS)
+        .map(|i| (-((i * i) as f64) / (2.0 * SIGMA * SIGMA)).exp())
+        .collect();
+    let total: f64 = weights.iter().sum();
+    let weights: Vec<f64> = weights.iter().map(|w| w / total).collect();
+
+    let pass = |input: &[f64], len: usize, at: &dyn Fn(usize, usize) -> usize| {
+        let mut output = vec![0.0; input.len()];
+        for (i, out) in output.iter_mut().enumerate() {
+            let (pos, line) = (i % len, i / len);
+            *out = weights
+                .iter()
+                .zip(-RADIUS..=RADIUS)
+                .map(|(w, offset)| {
+                    let pos = (pos as isize + offset).clamp(0, len as isize - 1) as usize;
+                    w * input[at(pos, line)]
+                })
+                .sum();
+        }
+        output
+    };
+
+    // The vertical pass writes its output column by column, so it is transposed back
+    let horizontal = pass(plane, width, &|x, y| y * width + x);
+    let vertical = pass(&horizontal, height, &|y, x| y * width + x);
+    (0..width * height)
+        .map(|i| vertical[(i % width) * height + i / width])
+        .collect()
+}
@@ -0,0 +1,83 @@
+use ssimulacra2::YuvConfig;
+
+/// Color options that take a value, which the resolved command replaces
+const COLOR_OPTIONS: [&str; 8] = [
+    "--src-matrix",
+    "--src-transfer",
+    "--src-primaries",
+    "--src-range",
+    "--dst-matrix",
+    "--dst-transfer",
+    "--dst-primaries",
+    "--dst-range",
+];
+
+/// Flags that the resolved command leaves out
+const DROPPED_FLAGS: [&str; 3] = ["--src-full-range", "--dst-full-range", "--print-command"];
+
+/// Rebuilds the command line `args` of this run for `--print-command`, with the color
+/// properties that were guessed or read from the inputs given as explicit options, so
+/// that the run can be reproduced exactly. Colors are written as their numeric codes,
+/// which every color option accepts.
+pub fn resolved_command(
+    args: &[String],
+    src_config: &YuvConfig,
+    dst_config: &YuvConfig,
+    self_temporal: bool,
+) -> String {
+    let options_end = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    // The resolved colors belong to the inputs after `--swap`, which swaps them back
+    let swap = args[..options_end].iter().any(|arg| arg == "--swap");
+    let (src_prefix, dst_prefix) = if swap { ("dst", "src") } else { ("src", "dst") };
+    let mut resolved = color_options(src_prefix, src_config);
+    // The distorted side of --self-temporal always shares the colors of the source
+    if !self_temporal {
+        resolved.extend(color_options(dst_prefix, dst_config));
+    }
+
+    let mut command = Vec::with_capacity(args.len() + resolved.len());
+    let mut options = args[..options_end].iter();
+    while let Some(arg) = options.next() {
+        let name = arg.split_once('=').map_or(arg.as_str(), |(name, _)| name);
+        if COLOR_OPTIONS.contains(&name) {
+            if !arg.contains('=') {
+                options.next();
+            }
+        } else if !DROPPED_FLAGS.contains(&name) {
+            command.push(shell_quote(arg));
+        }
+    }
+    // Placed before `--`, after which everything is an input
+    command.extend(resolved);
+    command.extend(args[options_end..].iter().map(|arg| shell_quote(arg)));
+    command.join(" ")
+}
+
+fn color_options(prefix: &str, config: &YuvConfig) -> Vec<String> {
+    let range = if config.full_range { "full" } else { "limited" };
+    vec![
+        format!("--{prefix}-matrix {}", config.matrix_coefficients as u8),
+        format!(
+            "--{prefix}-transfer {}",
+            config.transfer_characteristics as u8
+        ),
+        format!("--{prefix}-primaries {}", config.color_primaries as u8),
+        format!("--{prefix}-range {range}"),
+    ]
+}
+
+/// Quotes `arg` for a POSIX shell if it contains anything but plain characters
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=,+@%".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
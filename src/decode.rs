@@ -0,0 +1,525 @@
+use std::fs::{self, File};
+use std::io::{self, stdin, BufRead, BufReader, Seek, Stdin};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use av_metrics_decoders::{
+    y4m::{new_decoder_from_file, new_decoder_from_stdin},
+    Decoder, Frame, Pixel, VapoursynthDecoder, VideoDetails, Y4MDecoder,
+};
+use num_traits::FromPrimitive;
+use ssimulacra2::{ColorPrimaries, MatrixCoefficients, TransferCharacteristic};
+use vapoursynth::prelude::*;
+
+use crate::apng::ApngDecoder;
+use crate::diagnostics::warning;
+use crate::error::Ssimu2Error;
+use crate::float_clip::FloatClipDecoder;
+use crate::pattern::{PatternDecoder, PATTERN_PREFIX};
+use crate::raw::{PipedFormat, RawDecoder};
+
+/// How many frames to search for the first keyframe before giving up
+const KEYFRAME_SEARCH_LIMIT: usize = 1000;
+/// How many times to retry a VapourSynth frame that failed to decode
+const VAPOURSYNTH_RETRIES: u32 = 3;
+/// Delay before the first retry, doubled on every further retry
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Any of the supported video inputs, so that callers don't need to be
+/// generic over every combination of source and distorted decoder.
+pub enum VideoInput {
+    /// A VapourSynth clip, along with the index of the next frame to read
+    Vapoursynth(VapoursynthDecoder, usize),
+    /// A VapourSynth clip with float samples, converted to integer samples
+    FloatClip(FloatClipDecoder),
+    Y4mFile(Y4MDecoder<BufReader<File>>),
+    Y4mStdin(Y4MDecoder<BufReader<Stdin>>),
+    RawStdin(RawDecoder<BufReader<Stdin>>),
+    Pattern(PatternDecoder),
+    Apng(Box<ApngDecoder>),
+}
+
+impl VideoInput {
+    /// Opens `input`, which is either `-`/`/dev/stdin` for piped y4m, a `.y4m` file,
+    /// a `.vpy` script, an animated `.png`/`.apng`, or any other video file that
+    /// LSMASHSource can index.
+    /// Piped input is read as headerless planar frames instead, or as y4m with a different
+    /// pixel format than its header declares, if `piped_format` is given.
+    pub fn open(input: &str, piped_format: Option<PipedFormat>) -> Result<Self, Ssimu2Error> {
+        let decode_error = |reason: String| Ssimu2Error::DecodeError {
+            input: input.to_string(),
+            reason,
+        };
+
+        if is_piped(input) {
+            match piped_format {
+                Some(PipedFormat::Raw(raw_format)) => {
+                    return Ok(VideoInput::RawStdin(RawDecoder::new(
+                        BufReader::new(stdin()),
+                        raw_format,
+                    )));
+                }
+                Some(PipedFormat::Y4m(pixel_format)) => {
+                    return RawDecoder::with_y4m_header(BufReader::new(stdin()), pixel_format)
+                        .map(VideoInput::RawStdin)
+                        .map_err(decode_error);
+                }
+                None => (),
+            }
+            return new_decoder_from_stdin()
+                .map(VideoInput::Y4mStdin)
+                .map_err(decode_error);
+        }
+
+        let path = Path::new(input);
+        fs::metadata(path).map_err(|e| decode_error(e.to_string()))?;
+        match path
+            .extension()
+            .map(|ext| ext.to_ascii_lowercase().to_string_lossy().into_owned())
+            .as_deref()
+        {
+            Some("y4m") => new_decoder_from_file(path)
+                .map(VideoInput::Y4mFile)
+                .map_err(decode_error),
+            Some("png" | "apng") => ApngDecoder::new(path)
+                .map(|decoder| VideoInput::Apng(Box::new(decoder)))
+                .map_err(decode_error),
+            Some("vpy") => VapoursynthDecoder::new_from_script(path)
+                .map(|decoder| VideoInput::Vapoursynth(decoder, 0))
+                .or_else(|e| open_float_clip(path, e))
+                .map_err(|e| decode_error(e.to_string())),
+            _ => VapoursynthDecoder::new_from_video(path)
+                .map(|decoder| VideoInput::Vapoursynth(decoder, 0))
+                .or_else(|e| open_float_clip(path, e))
+                .map_err(|e| {
+                    let reason = format!("{e:#}");
+                    if is_missing_video_track(&reason) {
+                        decode_error(format!("no video track found in {input}"))
+                    } else {
+                        decode_error(reason)
+                    }
+                }),
+        }
+    }
+
+    /// Reads and discards the next `count` frames. Returns `false` if the input ran out.
+    pub fn skip_frames(&mut self, count: usize) -> bool {
+        let high_bit_depth = self.get_bit_depth() > 8;
+        (0..count).all(|_| {
+            if high_bit_depth {
+                self.read_video_frame::<u16>().is_some()
+            } else {
+                self.read_video_frame::<u8>().is_some()
+            }
+        })
+    }
+
+    /// Returns the number of frames in the input, if it can be known without decoding it
+    pub fn frame_count(&self) -> Option<usize> {
+        match self {
+            VideoInput::Vapoursynth(decoder, _) => decoder.get_frame_count().ok(),
+            VideoInput::FloatClip(decoder) => decoder.frame_count(),
+            VideoInput::Apng(decoder) => Some(decoder.frame_count()),
+            VideoInput::Y4mFile(_)
+            | VideoInput::Y4mStdin(_)
+            | VideoInput::RawStdin(_)
+            | VideoInput::Pattern(_) => None,
+        }
+    }
+}
+
+impl VideoInput {
+    /// Reads the next frame. Returns `Ok(None)` at the end of the input, and the reason
+    /// if a frame can't be decoded, so that a broken input isn't mistaken for a short one.
+    pub fn read_frame<T: Pixel>(&mut self) -> Result<Option<Frame<T>>, String> {
+        match self {
+            VideoInput::Vapoursynth(decoder, next_frame) => {
+                read_vapoursynth_frame(decoder, next_frame)
+            }
+            VideoInput::FloatClip(decoder) => decoder.read_frame(),
+            VideoInput::Y4mFile(decoder) => Ok(decoder.read_video_frame()),
+            VideoInput::Y4mStdin(decoder) => Ok(decoder.read_video_frame()),
+            VideoInput::RawStdin(decoder) => decoder.read_frame(),
+            VideoInput::Pattern(decoder) => Ok(decoder.read_video_frame()),
+            VideoInput::Apng(decoder) => decoder.read_frame(),
+        }
+    }
+}
+
+impl Decoder for VideoInput {
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        self.read_frame().unwrap_or_else(|reason| {
+            warning!("Failed to decode frame: {reason}");
+            None
+        })
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        match self {
+            VideoInput::Vapoursynth(decoder, _) => decoder.get_bit_depth(),
+            VideoInput::FloatClip(decoder) => decoder.get_bit_depth(),
+            VideoInput::Y4mFile(decoder) => decoder.get_bit_depth(),
+            VideoInput::Y4mStdin(decoder) => decoder.get_bit_depth(),
+            VideoInput::RawStdin(decoder) => decoder.get_bit_depth(),
+            VideoInput::Pattern(decoder) => decoder.get_bit_depth(),
+            VideoInput::Apng(decoder) => decoder.get_bit_depth(),
+        }
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        match self {
+            VideoInput::Vapoursynth(decoder, _) => decoder.get_video_details(),
+            VideoInput::FloatClip(decoder) => decoder.get_video_details(),
+            VideoInput::Y4mFile(decoder) => decoder.get_video_details(),
+            VideoInput::Y4mStdin(decoder) => decoder.get_video_details(),
+            VideoInput::RawStdin(decoder) => decoder.get_video_details(),
+            VideoInput::Pattern(decoder) => decoder.get_video_details(),
+            VideoInput::Apng(decoder) => decoder.get_video_details(),
+        }
+    }
+}
+
+/// Reads the next frame of a VapourSynth clip. Frames can fail to decode transiently,
+/// e.g. when the source lives on network storage, so failures before the end of the
+/// clip are retried with a backoff before giving up.
+fn read_vapoursynth_frame<T: Pixel>(
+    decoder: &mut VapoursynthDecoder,
+    next_frame: &mut usize,
+) -> Result<Option<Frame<T>>, String> {
+    let frame_count = decoder.get_frame_count().ok();
+    let mut attempt = 0;
+    loop {
+        if let Some(frame) = decoder.read_video_frame() {
+            *next_frame += 1;
+            return Ok(Some(frame));
+        }
+        // Running past the end of the clip is not an error
+        if frame_count.map_or(true, |frame_count| *next_frame >= frame_count) {
+            return Ok(None);
+        }
+        if attempt == VAPOURSYNTH_RETRIES {
+            return Err(format!(
+                "frame {next_frame} still failed to decode after {attempt} retries"
+            ));
+        }
+        attempt += 1;
+        let delay = RETRY_BACKOFF * 2u32.pow(attempt - 1);
+        warning!(
+            "Failed to decode frame {next_frame}, retrying in {delay:?} \
+             ({attempt}/{VAPOURSYNTH_RETRIES})"
+        );
+        thread::sleep(delay);
+    }
+}
+
+/// The VapourSynth decoder only accepts integer clips, so clips it rejects for their
+/// sample type are opened again as float clips. Any other error is passed on.
+fn open_float_clip(path: &Path, error: anyhow::Error) -> anyhow::Result<VideoInput> {
+    if !error.to_string().contains("only integer input") {
+        return Err(error);
+    }
+    FloatClipDecoder::open(path).map(VideoInput::FloatClip)
+}
+
+/// LSMASHSource fails with a message about the video track (or stream) when a file
+/// has no video, e.g. audio-only containers. Audio tracks are ignored otherwise.
+fn is_missing_video_track(reason: &str) -> bool {
+    let reason = reason.to_ascii_lowercase();
+    reason.contains("video track") || reason.contains("video stream")
+}
+
+/// Opens a `.vpy` script, or any other video file through LSMASHSource, as a VapourSynth
+/// environment for tools that need more than frames, e.g. frame properties.
+pub fn vapoursynth_environment(path: &Path) -> anyhow::Result<Environment> {
+    if path
+        .extension()
+        .map(|ext| ext.to_ascii_lowercase().to_string_lossy() == "vpy")
+        .unwrap_or(false)
+    {
+        return Ok(Environment::from_file(path, EvalFlags::SetWorkingDir)?);
+    }
+    Ok(Environment::from_script(&format!(
+        r#"
+import vapoursynth as vs
+
+core = vs.core
+
+clip = core.lsmas.LWLibavSource(source="{}")
+clip.set_output(0)
+"#,
+        path.canonicalize()?
+            .to_string_lossy()
+            .trim_start_matches(r"\\?\")
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+    ))?)
+}
+
+/// Color metadata declared by an input, with `None` for anything it leaves unspecified
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeclaredColor {
+    pub matrix: Option<MatrixCoefficients>,
+    pub transfer: Option<TransferCharacteristic>,
+    pub primaries: Option<ColorPrimaries>,
+    pub full_range: Option<bool>,
+}
+
+/// Reads the color metadata of `input`: the `_Matrix`, `_Transfer`, `_Primaries` and
+/// `_ColorRange` properties of the first VapourSynth frame, or only the range from the
+/// `XCOLORRANGE` parameter of a y4m header. Piped input can't be inspected without
+/// consuming it, so it never declares anything.
+pub fn detect_color(input: &str) -> DeclaredColor {
+    if is_piped(input) {
+        return DeclaredColor::default();
+    }
+    if input.starts_with(PATTERN_PREFIX) {
+        return DeclaredColor {
+            matrix: Some(MatrixCoefficients::BT709),
+            transfer: Some(TransferCharacteristic::BT1886),
+            primaries: Some(ColorPrimaries::BT709),
+            full_range: Some(false),
+        };
+    }
+    let path = Path::new(input);
+    if is_apng(path) {
+        // Decoded to full range BT.709 YCbCr from sRGB
+        return DeclaredColor {
+            matrix: Some(MatrixCoefficients::BT709),
+            transfer: Some(TransferCharacteristic::SRGB),
+            primaries: Some(ColorPrimaries::BT709),
+            full_range: Some(true),
+        };
+    }
+    if is_y4m(path) {
+        return DeclaredColor {
+            full_range: y4m_color_range(path),
+            ..DeclaredColor::default()
+        };
+    }
+    vapoursynth_color(path).unwrap_or_default()
+}
+
+/// Whether `path` is decoded as an (animated) PNG instead of through VapourSynth
+fn is_apng(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| {
+            matches!(
+                ext.to_ascii_lowercase().to_string_lossy().as_ref(),
+                "png" | "apng"
+            )
+        })
+        .unwrap_or(false)
+}
+
+fn is_y4m(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_ascii_lowercase().to_string_lossy() == "y4m")
+        .unwrap_or(false)
+}
+
+fn y4m_color_range(path: &Path) -> Option<bool> {
+    let mut header = Vec::new();
+    BufReader::new(File::open(path).ok()?)
+        .read_until(b'\n', &mut header)
+        .ok()?;
+    String::from_utf8_lossy(&header)
+        .split_ascii_whitespace()
+        .find_map(|param| match param {
+            "XCOLORRANGE=FULL" => Some(true),
+            "XCOLORRANGE=LIMITED" => Some(false),
+            _ => None,
+        })
+}
+
+fn vapoursynth_color(path: &Path) -> Option<DeclaredColor> {
+    let env = vapoursynth_environment(path).ok()?;
+    let (node, _) = env.get_output(0).ok()?;
+    let frame = node.get_frame(0).ok()?;
+    // Float samples are always full range, whatever the clip was converted from
+    let is_float = frame.format().sample_type() == SampleType::Float;
+    let props = frame.props();
+    let prop = |key| {
+        props
+            .get_int(key)
+            .ok()
+            .and_then(|value| u8::try_from(value).ok())
+    };
+    Some(DeclaredColor {
+        matrix: prop("_Matrix")
+            .and_then(MatrixCoefficients::from_u8)
+            .filter(|matrix| {
+                !matches!(
+                    matrix,
+                    MatrixCoefficients::Unspecified | MatrixCoefficients::Reserved
+                )
+            }),
+        transfer: prop("_Transfer")
+            .and_then(TransferCharacteristic::from_u8)
+            .filter(|transfer| {
+                !matches!(
+                    transfer,
+                    TransferCharacteristic::Unspecified
+                        | TransferCharacteristic::Reserved
+                        | TransferCharacteristic::Reserved0
+                )
+            }),
+        primaries: prop("_Primaries")
+            .and_then(ColorPrimaries::from_u8)
+            .filter(|primaries| {
+                !matches!(
+                    primaries,
+                    ColorPrimaries::Unspecified
+                        | ColorPrimaries::Reserved
+                        | ColorPrimaries::Reserved0
+                )
+            }),
+        // VapourSynth uses 0 for full range and 1 for limited range
+        full_range: match prop("_ColorRange") {
+            _ if is_float => Some(true),
+            Some(0) => Some(true),
+            Some(1) => Some(false),
+            _ => None,
+        },
+    })
+}
+
+/// Reads the sample aspect ratio of `input`: the `_SARNum` and `_SARDen` properties of
+/// the first VapourSynth frame, or the `A` parameter of a y4m header. Returns `None` if
+/// the input doesn't declare one, which means square pixels.
+pub fn detect_sar(input: &str) -> Option<(u64, u64)> {
+    if is_piped(input) || input.starts_with(PATTERN_PREFIX) {
+        return None;
+    }
+    let path = Path::new(input);
+    if is_apng(path) {
+        return None;
+    }
+    let sar = if is_y4m(path) {
+        y4m_sar(path)
+    } else {
+        vapoursynth_sar(path)
+    };
+    // 0:0 is how both declare an unknown aspect ratio
+    sar.filter(|&(num, den)| num > 0 && den > 0)
+}
+
+fn y4m_sar(path: &Path) -> Option<(u64, u64)> {
+    let mut header = Vec::new();
+    BufReader::new(File::open(path).ok()?)
+        .read_until(b'\n', &mut header)
+        .ok()?;
+    String::from_utf8_lossy(&header)
+        .split_ascii_whitespace()
+        .find_map(|param| {
+            let (num, den) = param.strip_prefix('A')?.split_once(':')?;
+            Some((num.parse().ok()?, den.parse().ok()?))
+        })
+}
+
+fn vapoursynth_sar(path: &Path) -> Option<(u64, u64)> {
+    let env = vapoursynth_environment(path).ok()?;
+    let (node, _) = env.get_output(0).ok()?;
+    let frame = node.get_frame(0).ok()?;
+    let props = frame.props();
+    let prop = |key| {
+        props
+            .get_int(key)
+            .ok()
+            .and_then(|value| u64::try_from(value).ok())
+    };
+    Some((prop("_SARNum")?, prop("_SARDen")?))
+}
+
+/// Returns the index of the first frame VapourSynth marks as an I frame in its
+/// `_PictType` property, or `None` if the input carries no picture types at all.
+/// Only inputs decoded through VapourSynth have picture types.
+pub fn first_keyframe(input: &str) -> anyhow::Result<Option<usize>> {
+    if !has_frame_props(input) {
+        return Ok(None);
+    }
+
+    let env = vapoursynth_environment(Path::new(input))?;
+    let (node, _) = env.get_output(0)?;
+    for i in 0..node.info().num_frames.min(KEYFRAME_SEARCH_LIMIT) {
+        let frame = node.get_frame(i).map_err(|e| anyhow!("{}", e))?;
+        match frame.props().get_data("_PictType") {
+            Ok(pict_type) if pict_type == b"I" => return Ok(Some(i)),
+            Ok(_) => (),
+            Err(_) => return Ok(None),
+        }
+    }
+    bail!("no keyframe in the first {KEYFRAME_SEARCH_LIMIT} frames")
+}
+
+/// Returns the indices of every frame VapourSynth marks as an I frame in its `_PictType`
+/// property, or `None` if the input carries no picture types at all.
+pub fn intra_frames(input: &str) -> anyhow::Result<Option<Vec<usize>>> {
+    if !has_frame_props(input) {
+        return Ok(None);
+    }
+
+    let env = vapoursynth_environment(Path::new(input))?;
+    let (node, _) = env.get_output(0)?;
+    let mut intra_frames = Vec::new();
+    for i in 0..node.info().num_frames {
+        let frame = node.get_frame(i).map_err(|e| anyhow!("{}", e))?;
+        match frame.props().get_data("_PictType") {
+            Ok(pict_type) if pict_type == b"I" => intra_frames.push(i),
+            Ok(_) => (),
+            Err(_) => return Ok(None),
+        }
+    }
+    Ok(Some(intra_frames))
+}
+
+/// Whether `input` is decoded through VapourSynth, the only way frames carry properties
+fn has_frame_props(input: &str) -> bool {
+    !(is_piped(input)
+        || input.starts_with(PATTERN_PREFIX)
+        || is_apng(Path::new(input))
+        || is_y4m(Path::new(input)))
+}
+
+/// Counts the frames of a y4m file by walking its frame headers, which is much faster
+/// than decoding but still touches every frame of the file. A truncated last frame
+/// isn't counted.
+pub fn count_y4m_frames(path: &Path, details: &VideoDetails) -> io::Result<usize> {
+    let bytes_per_sample = if details.bit_depth > 8 { 2 } else { 1 };
+    let chroma_samples = match details.chroma_sampling.get_decimation() {
+        Some((ss_x, ss_y)) => {
+            2 * ((details.width + ss_x) >> ss_x) * ((details.height + ss_y) >> ss_y)
+        }
+        None => 0,
+    };
+    let frame_size = ((details.width * details.height + chroma_samples) * bytes_per_sample) as i64;
+    let file_size = fs::metadata(path)?.len();
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut line = Vec::new();
+    // Stream header
+    reader.read_until(b'\n', &mut line)?;
+    let mut count = 0;
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(count);
+        }
+        if !line.starts_with(b"FRAME") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("missing header for frame {count}"),
+            ));
+        }
+        reader.seek_relative(frame_size)?;
+        if reader.stream_position()? > file_size {
+            return Ok(count);
+        }
+        count += 1;
+    }
+}
+
+pub fn is_piped(input: &str) -> bool {
+    input == "-" || input == "/dev/stdin"
+}
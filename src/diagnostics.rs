@@ -0,0 +1,96 @@
+//! Diagnostics that are printed to stderr, or emitted as `tracing` events with the
+//! `tracing` feature so that a host application can capture and filter them.
+
+/// Reports a problem that doesn't stop the comparison
+macro_rules! warning {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        eprintln!("WARNING: {}", format_args!($($arg)*));
+    }};
+}
+
+/// Reports a decision the user may want to know about, e.g. a generated seed
+#[cfg(feature = "video")]
+macro_rules! note {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::info!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        eprintln!($($arg)*);
+    }};
+}
+
+/// Prints a line of the human readable results, to stdout unless `--per-frame-stdout`
+/// reserved stdout for the per-frame scores
+#[cfg(feature = "video")]
+macro_rules! report {
+    ($($arg:tt)*) => {{
+        if $crate::diagnostics::reports_to_stderr() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    }};
+}
+
+#[cfg(feature = "video")]
+static REPORT_TO_STDERR: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Sends everything printed with `report!` to stderr from now on
+#[cfg(feature = "video")]
+pub fn report_to_stderr() {
+    REPORT_TO_STDERR.store(true, std::sync::atomic::Ordering::Relaxed);
+}
+
+#[cfg(feature = "video")]
+pub fn reports_to_stderr() -> bool {
+    REPORT_TO_STDERR.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+#[cfg(feature = "video")]
+pub(crate) use note;
+#[cfg(feature = "video")]
+pub(crate) use report;
+pub(crate) use warning;
+
+/// Installs a subscriber that prints events the same way as builds without the
+/// `tracing` feature, so the console output doesn't change.
+#[cfg(feature = "tracing")]
+pub fn init_subscriber() {
+    use std::fmt;
+
+    use tracing::{Event, Level, Subscriber};
+    use tracing_subscriber::fmt::{format::Writer, FmtContext, FormatEvent, FormatFields};
+    use tracing_subscriber::registry::LookupSpan;
+
+    struct ConsoleFormat;
+
+    impl<S, N> FormatEvent<S, N> for ConsoleFormat
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        fn format_event(
+            &self,
+            ctx: &FmtContext<'_, S, N>,
+            mut writer: Writer<'_>,
+            event: &Event<'_>,
+        ) -> fmt::Result {
+            match *event.metadata().level() {
+                Level::ERROR => write!(writer, "ERROR: ")?,
+                Level::WARN => write!(writer, "WARNING: ")?,
+                _ => (),
+            }
+            ctx.field_format().format_fields(writer.by_ref(), event)?;
+            writeln!(writer)
+        }
+    }
+
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(Level::INFO)
+        .event_format(ConsoleFormat)
+        .init();
+}
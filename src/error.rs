@@ -0,0 +1,89 @@
+use std::io;
+
+use thiserror::Error;
+
+/// Errors that can occur while comparing videos
+#[derive(Debug, Error)]
+pub enum Ssimu2Error {
+    /// An input could not be opened, or one of its frames could not be decoded.
+    #[error("Failed to decode {input}: {reason}")]
+    DecodeError { input: String, reason: String },
+
+    /// An input given as a URL could not be downloaded.
+    #[error("Failed to download {url}: {reason}")]
+    Download { url: String, reason: String },
+
+    /// The source and distorted inputs do not have the same width and height.
+    #[error("Resolution mismatch: source {}x{} != distorted {}x{}", src.0, src.1, dst.0, dst.1)]
+    ResolutionMismatch {
+        src: (usize, usize),
+        dst: (usize, usize),
+    },
+
+    /// The source and distorted inputs do not have the same number of frames.
+    #[error("Frame count mismatch: source has {src} frames, distorted has {dst}")]
+    FrameCountMismatch { src: usize, dst: usize },
+
+    /// `--range` ends after the last frame of the video.
+    #[error("Range ends at {end}, past the end of the video ({duration})")]
+    RangeOutOfBounds { end: String, duration: String },
+
+    /// `--frame-list` could not be read or parsed.
+    #[error("Invalid frame list: {reason}")]
+    FrameList { reason: String },
+
+    /// A time based option was given, but the source has no usable frame rate.
+    #[error("{option} needs a frame rate, but {input} does not declare one; pass --fps")]
+    MissingFrameRate { option: &'static str, input: String },
+
+    /// The source and distorted inputs are the same file, and `--strict` was given.
+    #[error("Source and distorted are the same file: {path}")]
+    SameInput { path: String },
+
+    /// Both inputs were told to read from stdin, which can only feed one of them.
+    #[error("Source and distorted inputs cannot both be from piped input")]
+    BothPiped,
+
+    /// A color matrix, transfer characteristics, color primaries or chroma subsampling
+    /// argument was not recognized.
+    #[error("Unrecognized {kind}: {value}")]
+    ColorParse { kind: &'static str, value: String },
+
+    /// Two outputs were both told to write to stdout.
+    #[error("{first} and {second} can't both write to stdout")]
+    StdoutConflict {
+        first: &'static str,
+        second: &'static str,
+    },
+
+    /// A frame selection that is drawn from the whole video was given, but the length of
+    /// the inputs can't be known before they are read, e.g. when both are piped.
+    #[error("{option} needs a known frame count, but neither input declares its length")]
+    UnknownFrameCount { option: &'static str },
+
+    /// Every frame was skipped by the frame selection, so there is nothing to summarize.
+    #[error("No frames were scored (check --increment/--skip-frames)")]
+    NoFramesScored,
+
+    /// `--no-guess` was given, but an input declares no value for a color property that
+    /// wasn't set on the command line either.
+    #[error(
+        "{input} does not declare its {property}, set it on the command line or drop --no-guess"
+    )]
+    UndeclaredColor {
+        input: String,
+        property: &'static str,
+    },
+
+    /// Some jobs of a batch failed, and `--keep-going` ran the others anyway.
+    #[error("{failed} of {total} batch job(s) failed")]
+    BatchFailures { failed: usize, total: usize },
+
+    /// Some images of a sequence failed, and `--keep-going` scored the others anyway.
+    #[error("{failed} of {total} image(s) failed")]
+    SequenceFailures { failed: usize, total: usize },
+
+    /// Reading an input or writing an output failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
@@ -0,0 +1,189 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+use av_metrics_decoders::{ChromaSampling, Decoder, Frame, Pixel, VideoDetails};
+use vapoursynth::format::Format;
+use vapoursynth::prelude::*;
+
+use crate::decode::vapoursynth_environment;
+
+/// Float clips are converted to integer samples of this bit depth, which keeps
+/// quantization far below anything SSIMULACRA2 can measure
+pub const FLOAT_BIT_DEPTH: usize = 16;
+
+/// Reads a VapourSynth clip with 32-bit float samples as full range integer frames of
+/// [`FLOAT_BIT_DEPTH`] bits, since scoring only works on integer samples.
+pub struct FloatClipDecoder {
+    env: Environment,
+    next_frame: usize,
+}
+
+impl FloatClipDecoder {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let decoder = Self {
+            env: vapoursynth_environment(path)?,
+            next_frame: 0,
+        };
+        let format = decoder.format()?;
+        if format.sample_type() != SampleType::Float || format.bytes_per_sample() != 4 {
+            bail!("only 32-bit float clips can be converted");
+        }
+        if format.color_family() == ColorFamily::RGB {
+            bail!("RGB clips are not supported, convert the clip to YUV first");
+        }
+        if chroma_sampling(&format).is_none() {
+            bail!(
+                "chroma subsampling of {}x{} is not supported, only 4:2:0, 4:2:2 and 4:4:4",
+                1 << format.sub_sampling_w(),
+                1 << format.sub_sampling_h()
+            );
+        }
+        Ok(decoder)
+    }
+
+    fn node(&self) -> anyhow::Result<Node<'_>> {
+        Ok(self.env.get_output(0)?.0)
+    }
+
+    fn format(&self) -> anyhow::Result<Format<'_>> {
+        match self.node()?.info().format {
+            Property::Constant(format) => Ok(format),
+            Property::Variable => Err(anyhow!("variable format clips are not supported")),
+        }
+    }
+
+    pub fn frame_count(&self) -> Option<usize> {
+        self.node().ok().map(|node| node.info().num_frames)
+    }
+
+    /// Reads frame `n` and converts its samples to integers, or `None` past the end
+    fn convert_frame<T: Pixel>(&self, n: usize) -> Result<Option<Frame<T>>, String> {
+        let details = self.get_video_details();
+        let node = self.node().map_err(|e| e.to_string())?;
+        if n >= node.info().num_frames {
+            return Ok(None);
+        }
+        let frame = node
+            .get_frame(n)
+            .map_err(|e| format!("failed to decode frame {n}: {e}"))?;
+
+        let mut output: Frame<T> =
+            Frame::new_with_padding(details.width, details.height, details.chroma_sampling, 0);
+        for (plane, out) in output
+            .planes
+            .iter_mut()
+            .enumerate()
+            .take(frame.format().plane_count())
+        {
+            let (width, height) = (out.cfg.width, out.cfg.height);
+            let mut samples = Vec::with_capacity(width * height * 2);
+            for y in 0..height {
+                for &value in &frame.plane_row::<f32>(plane, y)[..width] {
+                    let sample = float_to_int(value, FLOAT_BIT_DEPTH, plane > 0);
+                    samples.extend_from_slice(&sample.to_le_bytes());
+                }
+            }
+            out.copy_from_raw_u8(&samples, width * 2, 2);
+        }
+        Ok(Some(output))
+    }
+
+    /// Reads the next frame, or `None` at the end of the clip
+    pub fn read_frame<T: Pixel>(&mut self) -> Result<Option<Frame<T>>, String> {
+        let output = self.convert_frame(self.next_frame)?;
+        if output.is_some() {
+            self.next_frame += 1;
+        }
+        Ok(output)
+    }
+}
+
+impl Decoder for FloatClipDecoder {
+    /// Decoding errors are only reported by [`read_frame`](Self::read_frame)
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        self.read_frame().ok().flatten()
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        FLOAT_BIT_DEPTH
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        let node = self.node().expect("Clip was opened");
+        let info = node.info();
+        let format = self.format().expect("Format was checked on open");
+        let (width, height) = match info.resolution {
+            Property::Constant(resolution) => (resolution.width, resolution.height),
+            Property::Variable => (0, 0),
+        };
+        let chroma_sampling = chroma_sampling(&format).expect("Subsampling was checked on open");
+        let mut details = VideoDetails {
+            width,
+            height,
+            bit_depth: FLOAT_BIT_DEPTH,
+            chroma_sampling,
+            ..VideoDetails::default()
+        };
+        // The time base is the duration of a single frame
+        if let Property::Constant(fps) = info.framerate {
+            (details.time_base.num, details.time_base.den) = (fps.denominator, fps.numerator);
+        }
+        details
+    }
+}
+
+/// Chroma sampling of a clip format, or `None` for layouts frames can't represent,
+/// such as 4:1:1 or 4:4:0
+fn chroma_sampling(format: &Format<'_>) -> Option<ChromaSampling> {
+    if format.color_family() == ColorFamily::Gray {
+        return Some(ChromaSampling::Cs400);
+    }
+    match (format.sub_sampling_w(), format.sub_sampling_h()) {
+        (0, 0) => Some(ChromaSampling::Cs444),
+        (1, 0) => Some(ChromaSampling::Cs422),
+        (1, 1) => Some(ChromaSampling::Cs420),
+        _ => None,
+    }
+}
+
+/// Converts a float sample to a full range integer sample of `bit_depth` bits, the way
+/// VapourSynth's resizers do: luma spans 0 to 1 and chroma -0.5 to 0.5, both scaled by
+/// the largest sample value of the bit depth, e.g. 1023 for 10-bit, with chroma
+/// centered on half of the range.
+fn float_to_int(value: f32, bit_depth: usize, chroma: bool) -> u16 {
+    let max = ((1u32 << bit_depth) - 1) as f32;
+    let offset = if chroma {
+        (1u32 << (bit_depth - 1)) as f32
+    } else {
+        0.0
+    };
+    (value * max + offset).round().clamp(0.0, max) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn float_to_int_scales_by_the_bit_depth() {
+        assert_eq!(float_to_int(0.0, 10, false), 0);
+        assert_eq!(float_to_int(1.0, 10, false), 1023);
+        assert_eq!(float_to_int(0.5, 10, false), 512);
+        assert_eq!(float_to_int(1.0, 8, false), 255);
+        assert_eq!(float_to_int(1.0, 16, false), 65535);
+    }
+
+    #[test]
+    fn float_to_int_centers_chroma() {
+        assert_eq!(float_to_int(0.0, 10, true), 512);
+        assert_eq!(float_to_int(0.25, 10, true), 768);
+        assert_eq!(float_to_int(0.0, 16, true), 32768);
+    }
+
+    #[test]
+    fn float_to_int_clamps_out_of_range_samples() {
+        assert_eq!(float_to_int(1.5, 10, false), 1023);
+        assert_eq!(float_to_int(-0.1, 10, false), 0);
+        assert_eq!(float_to_int(-0.75, 10, true), 0);
+    }
+}
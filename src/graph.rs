@@ -0,0 +1,248 @@
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageEncoder};
+
+/// How `--graph` renders the chart
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum GraphBackend {
+    /// Full chart with a caption and axis descriptions. Needs a system font.
+    #[default]
+    Plotters,
+    /// Plain chart drawn with built-in bitmap digits, which never depends on fonts
+    Pure,
+}
+
+/// What `--graph` plots
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum GraphType {
+    /// Score of every frame over time
+    #[default]
+    Line,
+    /// Box and whiskers of the score distribution
+    Box,
+    /// Estimated density of the score distribution
+    Violin,
+}
+
+/// Color type of the PNG written by `--graph`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum GraphColor {
+    /// 8-bit RGB
+    #[default]
+    Rgb,
+    /// 8-bit RGB with an opaque alpha channel
+    Rgba,
+    /// 8-bit grayscale, using the BT.709 luma of the chart colors
+    Gray,
+}
+
+impl GraphColor {
+    fn color_type(self) -> ColorType {
+        match self {
+            GraphColor::Rgb => ColorType::Rgb8,
+            GraphColor::Rgba => ColorType::Rgba8,
+            GraphColor::Gray => ColorType::L8,
+        }
+    }
+
+    /// Converts a rendered RGB buffer into this color type
+    fn convert(self, rgb: &[u8]) -> Vec<u8> {
+        let channels = usize::from(self.color_type().channel_count());
+        let mut pixels = Vec::with_capacity(rgb.len() / 3 * channels);
+        for pixel in rgb.chunks_exact(3) {
+            match self {
+                GraphColor::Rgb => pixels.extend_from_slice(pixel),
+                GraphColor::Rgba => pixels.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]),
+                GraphColor::Gray => {
+                    let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(f32::from);
+                    pixels.push((0.2126 * r + 0.7152 * g + 0.0722 * b).round() as u8);
+                }
+            }
+        }
+        pixels
+    }
+}
+
+const MARGIN_LEFT: usize = 50;
+const MARGIN_RIGHT: usize = 20;
+const MARGIN_TOP: usize = 20;
+const MARGIN_BOTTOM: usize = 30;
+/// Each glyph pixel is drawn as a square of this many pixels
+const GLYPH_SCALE: usize = 2;
+
+const GRID: [u8; 3] = [77, 77, 77];
+const AXIS: [u8; 3] = [255, 255, 255];
+const AREA: [u8; 3] = [0, 128, 128];
+const BORDER: [u8; 3] = [0, 255, 255];
+
+/// 3x5 glyphs for the digits 0-9, one row per byte with the leftmost pixel in bit 2
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+const MINUS: [u8; 5] = [0b000, 0b000, 0b111, 0b000, 0b000];
+
+/// Range of the score axis of every graph: 0 to 100, widened to the next multiple of 10
+/// when scores fall outside of it, so that none of them are clipped
+pub fn score_range(results: &[f64]) -> (f64, f64) {
+    let (min, max) = results
+        .iter()
+        .fold((0.0f64, 100.0f64), |(min, max), &score| {
+            (min.min(score), max.max(score))
+        });
+    ((min / 10.0).floor() * 10.0, (max / 10.0).ceil() * 10.0)
+}
+
+/// A minimal RGB canvas, drawn on without any font or path rendering support
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height * 3],
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < self.width && y < self.height {
+            let offset = (y * self.width + x) * 3;
+            self.pixels[offset..offset + 3].copy_from_slice(&color);
+        }
+    }
+
+    fn hline(&mut self, x0: usize, x1: usize, y: usize, color: [u8; 3]) {
+        for x in x0..=x1 {
+            self.set(x, y, color);
+        }
+    }
+
+    fn vline(&mut self, x: usize, y0: usize, y1: usize, color: [u8; 3]) {
+        for y in y0.min(y1)..=y0.max(y1) {
+            self.set(x, y, color);
+        }
+    }
+
+    /// Draws `number` with its top right corner at (`right`, `top`)
+    fn number(&mut self, number: i64, right: usize, top: usize) {
+        let glyph_width = 4 * GLYPH_SCALE;
+        let text = number.to_string();
+        let left = right.saturating_sub(text.len() * glyph_width);
+        for (i, digit) in text.bytes().enumerate() {
+            let glyph = match digit {
+                b'-' => &MINUS,
+                digit => &DIGITS[(digit - b'0') as usize],
+            };
+            for (row, bits) in glyph.iter().enumerate() {
+                for col in 0..3 {
+                    if bits & (0b100 >> col) == 0 {
+                        continue;
+                    }
+                    for dy in 0..GLYPH_SCALE {
+                        for dx in 0..GLYPH_SCALE {
+                            self.set(
+                                left + i * glyph_width + col * GLYPH_SCALE + dx,
+                                top + row * GLYPH_SCALE + dy,
+                                AXIS,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders the per-frame scores as a filled area chart on the scale of [`score_range`],
+/// using only plain pixel operations
+pub fn write_pure_graph(
+    path: &Path,
+    results: &[f64],
+    (width, height): (u32, u32),
+    color: GraphColor,
+) -> io::Result<()> {
+    let mut canvas = Canvas::new(width as usize, height as usize);
+    let (x0, x1) = (MARGIN_LEFT, canvas.width - MARGIN_RIGHT);
+    let (y0, y1) = (MARGIN_TOP, canvas.height - MARGIN_BOTTOM);
+    let (low, high) = score_range(results);
+    let y_of = |score: f64| {
+        y1 - ((score.clamp(low, high) - low) / (high - low) * (y1 - y0) as f64) as usize
+    };
+    let glyph_height = 5 * GLYPH_SCALE;
+
+    for score in (low as i64..=high as i64).step_by(10) {
+        let y = y_of(score as f64);
+        canvas.hline(x0, x1, y, GRID);
+        if score % 20 == 0 {
+            canvas.number(score, x0 - 8, y.saturating_sub(glyph_height / 2));
+        }
+    }
+
+    if !results.is_empty() {
+        let plot_width = x1 - x0;
+        let mut previous_top = None;
+        for x in x0..=x1 {
+            let frame = ((x - x0) * results.len() / (plot_width + 1)).min(results.len() - 1);
+            let top = y_of(results[frame]);
+            canvas.vline(x, top, y1, AREA);
+            // Joins the border to the previous column, so steep changes stay connected
+            canvas.vline(x, top, previous_top.unwrap_or(top), BORDER);
+            previous_top = Some(top);
+        }
+
+        for tick in 0..=4 {
+            let frame = (results.len() - 1) * tick / 4;
+            let x = x0 + frame * plot_width / (results.len() - 1).max(1);
+            canvas.vline(x, y1, y1 + 4, AXIS);
+            let label_width = frame.to_string().len() * 4 * GLYPH_SCALE;
+            canvas.number(frame as i64, x + label_width / 2, y1 + 8);
+        }
+    }
+
+    canvas.vline(x0, y0, y1, AXIS);
+    canvas.hline(x0, x1, y1, AXIS);
+
+    save_graph(path, &canvas.pixels, width, height, color)
+}
+
+/// Writes a rendered RGB graph as a PNG of the given color type to `path`, or to stdout
+/// if `path` is `-`
+pub fn save_graph(
+    path: &Path,
+    rgb: &[u8],
+    width: u32,
+    height: u32,
+    color: GraphColor,
+) -> io::Result<()> {
+    let converted;
+    let pixels = if color == GraphColor::Rgb {
+        rgb
+    } else {
+        converted = color.convert(rgb);
+        &converted
+    };
+    if path == Path::new("-") {
+        let mut stdout = BufWriter::new(io::stdout().lock());
+        PngEncoder::new(&mut stdout)
+            .write_image(pixels, width, height, color.color_type().into())
+            .map_err(io::Error::other)?;
+        return stdout.flush();
+    }
+    image::save_buffer(path, pixels, width, height, color.color_type()).map_err(io::Error::other)
+}
@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use av_metrics_decoders::{Frame, Pixel};
+use ssimulacra2::{TransferCharacteristic, YuvConfig};
+
+/// Luminance of diffuse white in HDR content, per ITU-R BT.2408
+const REFERENCE_WHITE_NITS: f64 = 203.0;
+
+/// Highlight weight of every scored frame for `--hdr-stats`, keyed by frame index
+pub type HighlightWeights = Mutex<BTreeMap<usize, f64>>;
+
+/// Whether the transfer characteristics describe HDR content
+pub fn is_hdr(transfer: TransferCharacteristic) -> bool {
+    matches!(
+        transfer,
+        TransferCharacteristic::PerceptualQuantizer | TransferCharacteristic::HybridLogGamma
+    )
+}
+
+/// How much a frame counts towards the highlight-weighted mean.
+///
+/// Every luma sample is converted to display luminance in nits, through the PQ EOTF or
+/// through the HLG inverse OETF and the OOTF of a 1000 nit display. Samples up to
+/// reference white (203 nits) count as 1, and brighter samples count as their luminance
+/// relative to reference white, so a 1000 nit highlight counts almost 5 times as much.
+/// The weight of the frame is the average over all of its samples, which makes a frame
+/// without highlights weigh exactly 1.
+///
+/// Luma is used in place of true luminance, which is close enough for weighting.
+pub fn highlight_weight<T: Pixel>(frame: &Frame<T>, config: &YuvConfig) -> f64 {
+    let plane = &frame.planes[0];
+    let (width, height) = (plane.cfg.width, plane.cfg.height);
+    let shift = config.bit_depth.saturating_sub(8);
+    let (black, range) = if config.full_range {
+        (0.0, f64::from((1u32 << config.bit_depth) - 1))
+    } else {
+        (f64::from(16u32 << shift), f64::from(219u32 << shift))
+    };
+
+    let mut sum = 0.0;
+    for row in plane.rows_iter().take(height) {
+        for &sample in &row[..width] {
+            let sample: u32 = sample.into();
+            let signal = ((f64::from(sample) - black) / range).clamp(0.0, 1.0);
+            let nits = match config.transfer_characteristics {
+                TransferCharacteristic::HybridLogGamma => hlg_nits(signal),
+                _ => pq_nits(signal),
+            };
+            sum += (nits / REFERENCE_WHITE_NITS).max(1.0);
+        }
+    }
+    sum / (width * height).max(1) as f64
+}
+
+/// SMPTE ST 2084 EOTF
+fn pq_nits(signal: f64) -> f64 {
+    const M1: f64 = 2610.0 / 16384.0;
+    const M2: f64 = 2523.0 / 4096.0 * 128.0;
+    const C1: f64 = 3424.0 / 4096.0;
+    const C2: f64 = 2413.0 / 4096.0 * 32.0;
+    const C3: f64 = 2392.0 / 4096.0 * 32.0;
+
+    let power = signal.powf(1.0 / M2);
+    let linear = ((power - C1).max(0.0) / (C2 - C3 * power)).powf(1.0 / M1);
+    linear * 10000.0
+}
+
+/// ARIB STD-B67 inverse OETF, followed by the OOTF of a 1000 nit display (gamma 1.2)
+fn hlg_nits(signal: f64) -> f64 {
+    const A: f64 = 0.178_832_77;
+    const B: f64 = 0.284_668_92;
+    const C: f64 = 0.559_910_73;
+
+    let scene = if signal <= 0.5 {
+        signal * signal / 3.0
+    } else {
+        (((signal - C) / A).exp() + B) / 12.0
+    };
+    1000.0 * scene.powf(1.2)
+}
+
+/// Mean of the scores weighted by the highlight weight of their frame.
+/// Frames without a weight are left out.
+pub fn weighted_mean(scores: &[(usize, f64)], weights: &BTreeMap<usize, f64>) -> f64 {
+    let (total, weight_sum) = scores
+        .iter()
+        .filter_map(|(frame, score)| weights.get(frame).map(|weight| (score, weight)))
+        .fold((0.0, 0.0), |(total, weight_sum), (score, weight)| {
+            (total + score * weight, weight_sum + weight)
+        });
+    total / weight_sum
+}
@@ -0,0 +1,69 @@
+use std::io;
+use std::path::Path;
+
+use image::imageops::{self, FilterType};
+use image::{ColorType, ImageBuffer};
+use ssimulacra2::LinearRgb;
+
+/// Colors of the heatmap from no difference to the largest, interpolated in between
+const RAMP: [[f32; 3]; 4] = [
+    [0.0, 0.0, 0.0],
+    [0.7, 0.0, 0.0],
+    [1.0, 0.8, 0.0],
+    [1.0, 1.0, 1.0],
+];
+
+/// Writes a colorized map of where `distorted` differs from `source` to `path`, as an
+/// 8-bit PNG. SSIMULACRA2 doesn't expose its error maps, so this is the absolute
+/// difference in linear light, averaged over the channels, as a proxy.
+///
+/// The scale is the same for every frame, so maps of different frames can be compared.
+/// The square root of the difference is shown, as most distortions are small.
+pub fn write_heatmap(source: &LinearRgb, distorted: &LinearRgb, path: &Path) -> io::Result<()> {
+    let (width, height) = (source.width(), source.height());
+    let resized;
+    let distorted = if (distorted.width(), distorted.height()) == (width, height) {
+        distorted.data()
+    } else {
+        // Compared at the resolution of the source, the way `--resize` scores it
+        let buffer = ImageBuffer::<image::Rgb<f32>, _>::from_raw(
+            distorted.width() as u32,
+            distorted.height() as u32,
+            distorted
+                .data()
+                .iter()
+                .flatten()
+                .copied()
+                .collect::<Vec<_>>(),
+        )
+        .expect("Frame data matches the frame dimensions");
+        resized = imageops::resize(&buffer, width as u32, height as u32, FilterType::Triangle)
+            .into_raw()
+            .chunks_exact(3)
+            .map(|chunk| [chunk[0], chunk[1], chunk[2]])
+            .collect::<Vec<_>>();
+        &resized
+    };
+
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for (src, dst) in source.data().iter().zip(distorted) {
+        let difference = src
+            .iter()
+            .zip(dst)
+            .map(|(src, dst)| (src - dst).abs())
+            .sum::<f32>()
+            / 3.0;
+        pixels.extend(colorize(difference.sqrt()));
+    }
+    image::save_buffer(path, &pixels, width as u32, height as u32, ColorType::Rgb8)
+        .map_err(io::Error::other)
+}
+
+/// Maps a value from 0 to 1 onto [`RAMP`]. Values above 1 are shown as 1.
+fn colorize(value: f32) -> [u8; 3] {
+    let position = value.clamp(0.0, 1.0) * (RAMP.len() - 1) as f32;
+    let index = (position.floor() as usize).min(RAMP.len() - 2);
+    let t = position - index as f32;
+    let (low, high) = (RAMP[index], RAMP[index + 1]);
+    [0, 1, 2].map(|c| ((low[c] + (high[c] - low[c]) * t) * 255.0).round() as u8)
+}
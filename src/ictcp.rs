@@ -0,0 +1,93 @@
+use ssimulacra2::{
+    ColorPrimaries, LinearRgb, MatrixCoefficients, Pixel, Rgb, TransferCharacteristic, Yuv,
+};
+
+/// Converts I, Ct and Cp to L', M' and S', the inverse of the BT.2100 ICtCp matrix
+const ICTCP_TO_LMS: [[f32; 3]; 3] = [
+    [1.0, 0.008_609_037, 0.111_029_625],
+    [1.0, -0.008_609_037, -0.111_029_625],
+    [1.0, 0.560_031_3, -0.320_627_2],
+];
+
+/// Converts linear LMS to linear BT.2020 RGB, the inverse of the BT.2100 LMS matrix
+const LMS_TO_RGB: [[f32; 3]; 3] = [
+    [3.436_606_7, -2.506_452, 0.069_845_42],
+    [-0.791_329_6, 1.983_600_5, -0.192_270_9],
+    [-0.025_949_9, -0.098_913_71, 1.124_863_6],
+];
+
+/// Whether frames with this matrix need [`ictcp_to_linear_rgb`], which the ssimulacra2
+/// crate doesn't do on its own: it treats ICtCp like a non-constant luminance YCbCr
+/// matrix derived from the primaries.
+pub fn is_ictcp(matrix: MatrixCoefficients) -> bool {
+    matrix == MatrixCoefficients::ICtCp
+}
+
+/// Decodes a BT.2100 ICtCp frame to the linear RGB that scoring expects: the I, Ct and
+/// Cp samples are converted to L'M'S', linearized with the transfer characteristics of
+/// the frame (PQ or HLG), and converted from LMS to BT.2020 RGB.
+///
+/// Chroma is upsampled by repeating the nearest sample. This is experimental and hasn't
+/// been validated against a reference implementation.
+pub fn ictcp_to_linear_rgb<T: Pixel>(yuv: &Yuv<T>) -> anyhow::Result<LinearRgb> {
+    let config = yuv.config();
+    let (width, height) = (yuv.width(), yuv.height());
+    let planes = yuv.data();
+    let shift = config.bit_depth.saturating_sub(8);
+    let (black, luma_range, chroma_range) = if config.full_range {
+        let max = ((1u32 << config.bit_depth) - 1) as f32;
+        (0.0, max, max)
+    } else {
+        (
+            (16u32 << shift) as f32,
+            (219u32 << shift) as f32,
+            (224u32 << shift) as f32,
+        )
+    };
+    let mid = (1u32 << (config.bit_depth - 1)) as f32;
+    let sample = |plane: usize, x: usize, y: usize| -> f32 {
+        let value: u32 = planes[plane].p(x, y).into();
+        value as f32
+    };
+
+    let mut lms = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let chroma_y = y >> config.subsampling_y;
+        for x in 0..width {
+            let chroma_x = x >> config.subsampling_x;
+            let ictcp = [
+                (sample(0, x, y) - black) / luma_range,
+                (sample(1, chroma_x, chroma_y) - mid) / chroma_range,
+                (sample(2, chroma_x, chroma_y) - mid) / chroma_range,
+            ];
+            lms.push(multiply(&ICTCP_TO_LMS, ictcp));
+        }
+    }
+
+    let lms = Rgb::new(
+        lms,
+        width,
+        height,
+        config.transfer_characteristics,
+        ColorPrimaries::BT709,
+    )?;
+    // No primaries are converted from BT.709 to BT.709, so this only linearizes
+    let lms = LinearRgb::try_from(lms)?;
+    let rgb = lms
+        .into_data()
+        .into_iter()
+        .map(|lms| multiply(&LMS_TO_RGB, lms))
+        .collect();
+    let rgb = Rgb::new(
+        rgb,
+        width,
+        height,
+        TransferCharacteristic::Linear,
+        ColorPrimaries::BT2020,
+    )?;
+    Ok(LinearRgb::try_from(rgb)?)
+}
+
+fn multiply(matrix: &[[f32; 3]; 3], [a, b, c]: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * a + row[1] * b + row[2] * c)
+}
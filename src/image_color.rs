@@ -0,0 +1,145 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use image::{ImageDecoder, ImageFormat, ImageReader};
+use png::{ScaledFloat, SourceChromaticities};
+use ssimulacra2::{ColorPrimaries, TransferCharacteristic};
+
+/// Maximum distance between declared and known chromaticity coordinates to consider them equal
+const CHROMATICITY_TOLERANCE: f32 = 0.005;
+
+/// Inspects the color space an image file declares, and returns the transfer
+/// characteristics and primaries it maps to. Returns `None` if the file declares
+/// nothing, or nothing that can be mapped.
+pub fn detect_image_color(path: &Path) -> Option<(TransferCharacteristic, ColorPrimaries)> {
+    let format = ImageFormat::from_path(path).ok();
+
+    // These formats always store linear light
+    if matches!(format, Some(ImageFormat::OpenExr | ImageFormat::Hdr)) {
+        return Some((TransferCharacteristic::Linear, ColorPrimaries::BT709));
+    }
+
+    let icc_profile = ImageReader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?
+        .icc_profile()
+        .ok()
+        .flatten();
+    if let Some(color) = icc_profile.as_deref().and_then(color_from_icc_profile) {
+        return Some(color);
+    }
+
+    if format == Some(ImageFormat::Png) {
+        return png_color(path);
+    }
+
+    None
+}
+
+/// Reads the sRGB, gAMA and cHRM chunks of a PNG
+fn png_color(path: &Path) -> Option<(TransferCharacteristic, ColorPrimaries)> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path).ok()?));
+    let reader = decoder.read_info().ok()?;
+    let info = reader.info();
+
+    if info.srgb.is_some() {
+        return Some((TransferCharacteristic::SRGB, ColorPrimaries::BT709));
+    }
+    if info.source_gamma.is_none() && info.source_chromaticities.is_none() {
+        return None;
+    }
+
+    // Per the PNG spec, a missing cHRM chunk means the primaries are those of sRGB
+    let transfer = match info.source_gamma {
+        Some(gamma) => transfer_from_gamma(gamma)?,
+        None => TransferCharacteristic::SRGB,
+    };
+    let primaries = match info.source_chromaticities {
+        Some(chromaticities) => primaries_from_chromaticities(chromaticities)?,
+        None => ColorPrimaries::BT709,
+    };
+    Some((transfer, primaries))
+}
+
+/// Maps the encoding gamma of a gAMA chunk to a pure power law transfer
+fn transfer_from_gamma(gamma: ScaledFloat) -> Option<TransferCharacteristic> {
+    let gamma = gamma.into_value();
+    [
+        (1.0, TransferCharacteristic::Linear),
+        (1.0 / 2.2, TransferCharacteristic::BT470M),
+        (1.0 / 2.8, TransferCharacteristic::BT470BG),
+    ]
+    .into_iter()
+    .find(|(known, _)| (gamma - known).abs() < 0.01)
+    .map(|(_, transfer)| transfer)
+}
+
+/// Matches the chromaticities of a cHRM chunk against the common primaries
+fn primaries_from_chromaticities(chrm: SourceChromaticities) -> Option<ColorPrimaries> {
+    let xy = |(x, y): (ScaledFloat, ScaledFloat)| (x.into_value(), y.into_value());
+    let declared = [xy(chrm.red), xy(chrm.green), xy(chrm.blue), xy(chrm.white)];
+    const D65: (f32, f32) = (0.3127, 0.3290);
+
+    [
+        (
+            [(0.640, 0.330), (0.300, 0.600), (0.150, 0.060), D65],
+            ColorPrimaries::BT709,
+        ),
+        (
+            [(0.708, 0.292), (0.170, 0.797), (0.131, 0.046), D65],
+            ColorPrimaries::BT2020,
+        ),
+        (
+            [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060), D65],
+            ColorPrimaries::P3Display,
+        ),
+        (
+            [
+                (0.680, 0.320),
+                (0.265, 0.690),
+                (0.150, 0.060),
+                (0.314, 0.351),
+            ],
+            ColorPrimaries::P3DCI,
+        ),
+    ]
+    .into_iter()
+    .find(|(known, _)| {
+        known.iter().zip(&declared).all(|(known, declared)| {
+            (known.0 - declared.0).abs() < CHROMATICITY_TOLERANCE
+                && (known.1 - declared.1).abs() < CHROMATICITY_TOLERANCE
+        })
+    })
+    .map(|(_, primaries)| primaries)
+}
+
+/// Guesses the color space of an ICC profile from its description. Parsing the actual
+/// curves is out of scope, but the common profiles are reliably named.
+fn color_from_icc_profile(profile: &[u8]) -> Option<(TransferCharacteristic, ColorPrimaries)> {
+    // Version 4 profiles store the description as UTF-16, so drop the zero bytes to
+    // search both versions the same way
+    let text: Vec<u8> = profile.iter().copied().filter(|&b| b != 0).collect();
+    let text = String::from_utf8_lossy(&text);
+
+    if text.contains("PQ") && (text.contains("2020") || text.contains("2100")) {
+        Some((
+            TransferCharacteristic::PerceptualQuantizer,
+            ColorPrimaries::BT2020,
+        ))
+    } else if text.contains("HLG") && (text.contains("2020") || text.contains("2100")) {
+        Some((
+            TransferCharacteristic::HybridLogGamma,
+            ColorPrimaries::BT2020,
+        ))
+    } else if text.contains("Display P3") {
+        Some((TransferCharacteristic::SRGB, ColorPrimaries::P3Display))
+    } else if text.contains("sRGB") {
+        Some((TransferCharacteristic::SRGB, ColorPrimaries::BT709))
+    } else {
+        None
+    }
+}
@@ -1,18 +1,115 @@
 #[cfg(feature = "video")]
+mod affinity;
+#[cfg(feature = "video")]
+mod apng;
+#[cfg(feature = "video")]
+mod batch;
+#[cfg(feature = "video")]
+mod bench;
+mod classic;
+#[cfg(feature = "video")]
+mod command;
+#[cfg(feature = "video")]
+mod decode;
+mod diagnostics;
+#[cfg(feature = "video")]
+mod error;
+#[cfg(feature = "video")]
+mod float_clip;
+#[cfg(feature = "video")]
+mod graph;
+#[cfg(feature = "video")]
+mod hdr;
+#[cfg(feature = "video")]
+mod heatmap;
+#[cfg(feature = "video")]
+mod ictcp;
+mod image_color;
+mod mask;
+#[cfg(feature = "video")]
+mod output;
+#[cfg(feature = "video")]
+mod overlay;
+#[cfg(feature = "video")]
+mod pattern;
+#[cfg(feature = "video")]
+mod raw;
+mod remote;
+mod selftest;
+#[cfg(feature = "video")]
+mod sequence;
+mod stride;
+#[cfg(feature = "video")]
+mod subsampling;
+#[cfg(feature = "video")]
 mod video;
 
+#[cfg(feature = "video")]
+use self::batch::read_jobs;
+#[cfg(feature = "video")]
+use self::bench::decode_bench;
+use self::classic::ClassicMetric;
+#[cfg(feature = "video")]
+use self::decode::is_piped;
+#[cfg(feature = "video")]
+use self::diagnostics::note;
+use self::diagnostics::warning;
+#[cfg(feature = "video")]
+use self::error::Ssimu2Error;
+#[cfg(feature = "video")]
+use self::graph::{GraphBackend, GraphColor, GraphType};
+use self::image_color::detect_image_color;
+use self::mask::Mask;
+#[cfg(feature = "video")]
+use self::output::{append_summary_csv, Emit};
+#[cfg(feature = "video")]
+use self::raw::{PipedFormat, PixelFormat, RawFormat};
+use self::remote::{is_url, Download};
+#[cfg(feature = "video")]
+use self::sequence::compare_sequences;
+use self::stride::stride_pixels;
 #[cfg(feature = "video")]
 use self::video::*;
-use clap::{Parser, Subcommand};
+#[cfg(feature = "video")]
+use clap::Args;
+use clap::{Parser, Subcommand, ValueEnum};
+use image::DynamicImage;
 #[cfg(feature = "video")]
 use ssimulacra2::MatrixCoefficients;
-use ssimulacra2::{compute_frame_ssimulacra2, ColorPrimaries, Rgb, TransferCharacteristic};
+use ssimulacra2::{
+    compute_frame_ssimulacra2, ColorPrimaries, LinearRgb, Rgb, TransferCharacteristic,
+};
+#[cfg(feature = "video")]
+use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
+#[cfg(feature = "video")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Crate version along with the commit and date it was built from, for bug reports
+const VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("SSIMULACRA2_GIT_HASH"),
+    " ",
+    env!("SSIMULACRA2_BUILD_DATE"),
+    ")"
+);
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version = VERSION, about, long_about = None)]
 #[command(propagate_version = true)]
 struct Cli {
+    /// Fail instead of warning when the inputs look like a mistake,
+    /// e.g. when source and distorted are the same file.
+    #[arg(long, global = true, verbatim_doc_comment)]
+    strict: bool,
+
+    /// Use the distorted input as the reference and score the source against it.
+    /// SSIMULACRA2 is asymmetric, so this changes the score. Per-input options such
+    /// as `--src-matrix` follow their input.
+    #[arg(long, global = true, verbatim_doc_comment)]
+    swap: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -23,184 +120,1614 @@ enum Commands {
     /// Compare two still images. Resolutions must be identical.
     Image {
         /// Source image
-        #[arg(help = "Original unmodified image", value_hint = clap::ValueHint::FilePath)]
+        #[arg(
+            help = "Original unmodified image, or an http(s) URL to download it from with curl",
+            value_hint = clap::ValueHint::FilePath
+        )]
         source: PathBuf,
 
         /// Distorted image
-        #[arg(help = "Distorted image", value_hint = clap::ValueHint::FilePath)]
-        distorted: PathBuf,
+        #[arg(
+            help = "Distorted image, or an http(s) URL to download it from with curl. \
+                    Not needed with --split.",
+            required_unless_present = "split",
+            value_hint = clap::ValueHint::FilePath
+        )]
+        distorted: Option<PathBuf>,
+
+        /// Score the two halves of the source image against each other instead of
+        /// comparing two images, for A/B renders stored side by side (horizontal) or
+        /// stacked (vertical). The left or top half is the reference, or the other
+        /// half with --swap.
+        #[arg(long, value_enum, conflicts_with = "distorted", verbatim_doc_comment)]
+        split: Option<Split>,
+
+        /// Only score the region of interest given by this mask image (alpha, or luma
+        /// if it has no alpha). Masked-out pixels are made to match the source.
+        #[arg(long, value_hint = clap::ValueHint::FilePath, verbatim_doc_comment)]
+        mask: Option<PathBuf>,
+
+        /// Also report these legacy metrics, as a comma separated list, computed on the
+        /// same decoded images. They always cover the whole image, ignoring `--mask`.
+        #[arg(long, value_enum, value_delimiter = ',', verbatim_doc_comment)]
+        also: Vec<ClassicMetric>,
+
+        /// Print PASS if the score is at least this value and FAIL otherwise,
+        /// and exit with status 1 on FAIL.
+        #[arg(long, value_name = "SCORE", verbatim_doc_comment)]
+        pass_threshold: Option<f64>,
+
+        /// Print the distortion, `100 - score`, instead of the score. --pass-threshold
+        /// still compares against the score.
+        #[arg(long, verbatim_doc_comment)]
+        invert: bool,
+
+        /// Only score every Nth pixel of each row, in a diagonal pattern, for a rough score
+        /// about N times faster. This changes what the metric sees, so the result is not a
+        /// standard SSIMULACRA2 score.
+        #[arg(
+            long,
+            value_name = "N",
+            value_parser = clap::value_parser!(u16).range(2..),
+            verbatim_doc_comment
+        )]
+        pixel_stride: Option<u16>,
     },
     /// Compare two videos. Resolutions and frame counts must be identical.
     #[cfg(feature = "video")]
-    Video {
-        /// Source video
-        #[arg(help = "Original unmodified video", value_hint = clap::ValueHint::FilePath)]
+    Video(VideoArgs),
+    /// Compare two sequences of numbered images, e.g. `frame_%04d.png`.
+    #[cfg(feature = "video")]
+    Sequence {
+        /// Source images
+        #[arg(help = "Pattern of the original unmodified images")]
         source: String,
 
-        /// Distorted video
-        #[arg(help = "Distorted video", value_hint = clap::ValueHint::FilePath)]
+        /// Distorted images
+        #[arg(help = "Pattern of the distorted images")]
         distorted: String,
 
-        /// How many worker threads to use for decoding & calculating scores.
-        /// Note: Memory usage increases linearly with the number of workers.
-        #[arg(long, short, verbatim_doc_comment)]
-        frame_threads: Option<usize>,
-
-        /// The amount of frames to skip.
+        /// Number of the first image to compare.
         #[arg(long, default_value_t = 0)]
-        skip_frames: usize,
+        start: usize,
 
-        /// Limit the amount of frames to compare.
+        /// How many images to compare. Defaults to every image up to the first missing one.
         #[arg(long)]
         frames: Option<usize>,
 
-        /// How to increment current frame count; e.g. 10 will read every 10th frame.
-        #[arg(long, short)]
-        increment: Option<usize>,
+        /// How many worker threads to use for calculating scores.
+        #[arg(long, short, default_value_t = 1)]
+        frame_threads: usize,
 
-        /// Whether to output a frame-by-frame graph of scores.
-        #[arg(long, short)]
-        graph: bool,
-
-        /// Will output scores for every frame followed by the average at the end.
+        /// Will output scores for every image followed by the average at the end.
         #[arg(long, short)]
         verbose: bool,
 
-        /// Source color matrix
-        #[arg(long)]
-        src_matrix: Option<String>,
+        /// Continue with the next image when one fails, e.g. on a corrupt file, and list
+        /// the failed images at the end. The exit status is still 1 if any image failed.
+        #[arg(long, verbatim_doc_comment)]
+        keep_going: bool,
+    },
+    /// Run the video comparisons listed in a jobs file one after another in a single
+    /// process, and summarize them all at the end.
+    #[cfg(feature = "video")]
+    Batch {
+        /// Jobs file
+        #[arg(
+            help = "File with one job per line: `source<TAB>distorted`, or a JSON object \
+                    with `source` and `distorted` fields",
+            value_hint = clap::ValueHint::FilePath
+        )]
+        jobs: PathBuf,
 
-        /// Source transfer characteristics
-        #[arg(long)]
-        src_transfer: Option<String>,
+        /// How many worker threads to use for calculating the scores of each job.
+        #[arg(long, short, default_value_t = 1)]
+        frame_threads: usize,
 
-        /// Source color primaries
-        #[arg(long)]
-        src_primaries: Option<String>,
+        /// Continue with the next job when one fails, e.g. on a corrupt file, and list
+        /// the failed jobs at the end. The exit status is still 1 if any job failed.
+        #[arg(long, verbatim_doc_comment)]
+        keep_going: bool,
+    },
+    /// Read every frame of a single video and discard it, and report how fast the
+    /// decoder is, without the cost of scoring.
+    #[cfg(feature = "video")]
+    DecodeBench {
+        /// Video to decode
+        #[arg(help = "Video to decode, or `-` for piped y4m")]
+        input: String,
 
-        /// The source is using full-range data
+        /// Stop after this many frames.
         #[arg(long)]
-        src_full_range: bool,
+        frames: Option<usize>,
 
-        /// Distorted color matrix
-        #[arg(long)]
-        dst_matrix: Option<String>,
+        /// Read piped input as headerless planar frames, like the video command.
+        #[arg(long, value_name = "WxH:PIXFMT:FPS")]
+        raw_format: Option<RawFormat>,
+    },
+    /// Check that this build scores synthetic frames as expected and can render graphs.
+    Selftest,
+}
 
-        /// Distorted transfer characteristics
-        #[arg(long)]
-        dst_transfer: Option<String>,
+#[cfg(feature = "video")]
+#[derive(Args, Debug)]
+#[command(group(clap::ArgGroup::new("dump_output").multiple(true)))]
+struct VideoArgs {
+    /// Source video
+    #[arg(
+        help = "Original unmodified video, an http(s) URL to download it from with curl, or \
+                `pattern:NAME` to score against a generated smpte-bars, gradient or noise \
+                pattern",
+        value_hint = clap::ValueHint::FilePath
+    )]
+    source: String,
 
-        /// Distorted color primaries
-        #[arg(long)]
-        dst_primaries: Option<String>,
+    /// Distorted video
+    #[arg(
+        help = "Distorted video, or an http(s) URL to download it from with curl",
+        required_unless_present = "self_temporal",
+        value_hint = clap::ValueHint::FilePath
+    )]
+    distorted: Option<String>,
 
-        /// The distorted video is using full-range data
-        #[arg(long)]
-        dst_full_range: bool,
-    },
+    /// How many worker threads to use for calculating scores.
+    /// Frames are decoded on one additional thread that feeds the workers.
+    /// Note: Memory usage increases linearly with the number of workers.
+    #[arg(long, short, verbatim_doc_comment)]
+    frame_threads: Option<usize>,
+
+    /// Approximate memory budget in megabytes. Lowers `--frame-threads` if the
+    /// estimated memory use at the input resolution would exceed it.
+    #[arg(long, value_name = "MB", verbatim_doc_comment)]
+    max_memory: Option<usize>,
+
+    /// How many decoded frame pairs may wait for a free worker thread. Defaults to twice
+    /// `--frame-threads`. Lower it to save memory at high resolutions, or raise it to
+    /// smooth over a decoder that delivers frames in bursts.
+    #[arg(long, value_name = "N", verbatim_doc_comment)]
+    buffer_frames: Option<usize>,
+
+    /// Read piped input as headerless planar frames instead of y4m, with the given
+    /// geometry, e.g. `1920x1080:yuv420p10le:24000/1001`. Supported pixel formats are
+    /// gray, yuv420p, yuv422p and yuv444p, optionally followed by 10le, 12le or 16le.
+    #[arg(long, value_name = "WxH:PIXFMT:FPS", verbatim_doc_comment)]
+    raw_format: Option<RawFormat>,
+
+    /// Read piped y4m with this pixel format instead of the one its header declares,
+    /// e.g. yuv420p10le, for muxers that write tags the y4m decoder misreads. The frame
+    /// size and rate are still taken from the header. Takes the same pixel formats as
+    /// --raw-format.
+    #[arg(
+        long,
+        value_name = "PIXFMT",
+        conflicts_with = "raw_format",
+        verbatim_doc_comment
+    )]
+    stdin_format: Option<PixelFormat>,
+
+    /// Start each input at its first keyframe, for re-encodes whose GOP boundaries moved.
+    /// Frame numbers in other options and the output count from the aligned start.
+    /// Needs inputs decoded through VapourSynth, which report picture types.
+    #[arg(long, verbatim_doc_comment)]
+    align_keyframes: bool,
+
+    /// Find the temporal offset between the inputs before the full run, by scoring the
+    /// first distorted frame against the source frames up to RADIUS frames away (and the
+    /// first source frame against the distorted ones), then skip frames to apply the best
+    /// match. The chosen offset is reported. Both inputs must be files.
+    #[arg(
+        long,
+        value_name = "RADIUS",
+        conflicts_with = "align_keyframes",
+        verbatim_doc_comment
+    )]
+    auto_align: Option<usize>,
+
+    /// Score each frame of the source against the frame after it, instead of against a
+    /// distorted video, to measure frame-to-frame instability such as flicker. Frame N
+    /// of the output compares frames N and N + 1. The source must be a file, as it is
+    /// decoded twice.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "distorted",
+            "align_keyframes",
+            "auto_align",
+            "dst_matrix",
+            "dst_transfer",
+            "dst_primaries",
+            "dst_subsampling",
+            "dst_range",
+            "dst_full_range",
+        ],
+        verbatim_doc_comment
+    )]
+    self_temporal: bool,
+
+    /// Warn if the source looks like it was already lossy encoded, e.g. when an encode
+    /// was passed as the reference by mistake. Checks how much the first frames of the
+    /// source change under a slight blur, which is only a heuristic.
+    #[arg(long, verbatim_doc_comment)]
+    verify_reference: bool,
+
+    /// Frame rate to use for every conversion between timestamps and frames, e.g. for
+    /// `--at` and `--range`, instead of the one the source declares. Takes a number or
+    /// a fraction like 24000/1001.
+    #[arg(long, value_parser = parse_fps, verbatim_doc_comment)]
+    fps: Option<f64>,
+
+    /// The amount of frames to skip.
+    #[arg(long, default_value_t = 0)]
+    skip_frames: usize,
+
+    /// Limit the amount of frames to compare.
+    #[arg(long)]
+    frames: Option<usize>,
+
+    /// How to increment current frame count; e.g. 10 will read every 10th frame.
+    #[arg(long, short)]
+    increment: Option<usize>,
+
+    /// Also score every frame that starts a new scene, in addition to the
+    /// frames selected by `--increment`. Requires the VapourSynth misc plugin.
+    #[arg(long, verbatim_doc_comment)]
+    include_scene_changes: bool,
+
+    /// Score this many randomly chosen frames instead of sampling every `--increment` frames.
+    #[arg(long)]
+    random_sample: Option<usize>,
+
+    /// Score only the I frames, instead of sampling every `--increment` frames. The
+    /// other frames are still decoded to keep the inputs aligned. Needs a source decoded
+    /// through VapourSynth, which reports picture types.
+    #[arg(long, conflicts_with = "random_sample", verbatim_doc_comment)]
+    iframes_only: bool,
+
+    /// Seed for `--random-sample`. A time-based seed is used and printed if omitted.
+    #[arg(long, requires = "random_sample")]
+    seed: Option<u64>,
+
+    /// Score only the frame shown at this timestamp (HH:MM:SS.mmm), based on `--fps` or the
+    /// source frame rate. May be given multiple times. Overrides all other frame selection
+    /// options.
+    #[arg(long, value_parser = parse_timestamp, verbatim_doc_comment)]
+    at: Vec<Duration>,
+
+    /// Score only the frames between two timestamps (START-END, each HH:MM:SS.mmm),
+    /// based on `--fps` or the source frame rate. Replaces `--skip-frames` and `--frames`.
+    #[arg(
+        long,
+        value_name = "START-END",
+        value_parser = parse_range,
+        conflicts_with_all = ["skip_frames", "frames"],
+        verbatim_doc_comment
+    )]
+    range: Option<(Duration, Duration)>,
+
+    /// Score exactly these frames instead of sampling every `--increment` frames. Takes a
+    /// file, `-` to read it from stdin, or a list of indices separated by commas, where
+    /// START-END includes a whole span.
+    #[arg(
+        long,
+        value_name = "PATH|LIST|-",
+        conflicts_with_all = ["random_sample", "iframes_only"],
+        verbatim_doc_comment
+    )]
+    frame_list: Option<String>,
+
+    /// Never score these frames, e.g. known corrupted source frames. Takes a file, or
+    /// a list of indices separated by commas, where START-END includes a whole span.
+    /// Excluded frames are still decoded to keep the inputs aligned.
+    #[arg(
+        long,
+        value_name = "PATH|LIST",
+        value_parser = parse_frame_set,
+        verbatim_doc_comment
+    )]
+    exclude_frames: Option<BTreeSet<usize>>,
+
+    /// Print the mean and minimum score of every scene, given the first frame of each
+    /// scene as a file or a list of indices separated by commas. A scene always starts
+    /// at frame 0, and each one lasts until the next cut.
+    #[arg(long, value_name = "PATH|LIST", value_parser = parse_frame_set, verbatim_doc_comment)]
+    scenes: Option<BTreeSet<usize>>,
+
+    /// Whether to output a frame-by-frame graph of scores.
+    #[arg(long, short)]
+    graph: bool,
+
+    /// Size of the graph image, formatted as WIDTHxHEIGHT.
+    #[arg(long, default_value = "1500x1000", value_parser = parse_graph_size)]
+    graph_size: (u32, u32),
+
+    /// How to render the graph. `pure` draws a plainer chart without any fonts,
+    /// for systems where the default backend fails to render text.
+    #[arg(long, value_enum, default_value_t, verbatim_doc_comment)]
+    graph_backend: GraphBackend,
+
+    /// What the graph shows: the score of every frame over time, or the distribution
+    /// of scores as a box plot or violin plot. Only `line` is supported by the `pure`
+    /// backend.
+    #[arg(long, value_enum, default_value_t, verbatim_doc_comment)]
+    graph_type: GraphType,
+
+    /// Color type of the graph PNG, for consumers that need RGBA or grayscale images.
+    #[arg(long, value_enum, default_value_t)]
+    graph_color: GraphColor,
+
+    /// Write the graph to this path instead of an automatically named file, or to stdout
+    /// with `-`, in which case everything else is printed to stderr. Implies `--graph`.
+    #[arg(long, value_hint = clap::ValueHint::FilePath, verbatim_doc_comment)]
+    graph_output: Option<PathBuf>,
+
+    /// Write these result files in one run, as a comma separated list: `png` for the
+    /// graph, `csv` for the score of every frame, and `json` for the scores and summary.
+    #[arg(long, value_enum, value_delimiter = ',', verbatim_doc_comment)]
+    emit: Vec<Emit>,
+
+    /// Print an ASCII histogram of the score distribution to the terminal.
+    #[arg(long)]
+    histogram: bool,
+
+    /// Print the summary as a table row (input | mean | median | p5 | p95 | min)
+    /// instead of the summary block.
+    #[arg(long, verbatim_doc_comment)]
+    table: bool,
+
+    /// Print the summary on a single line, as `mean=X median=X p5=X p95=X n=FRAMES`,
+    /// instead of the summary block. Easier to grep for in aggregated logs.
+    #[arg(long, conflicts_with = "table", verbatim_doc_comment)]
+    oneline: bool,
+
+    /// How the 5th and 95th percentiles are computed, named like the methods of numpy's
+    /// `percentile` to match results against it. The default, median-unbiased, is the
+    /// method statrs uses (Hyndman and Fan's definition 8). numpy defaults to linear.
+    #[arg(long, value_enum, default_value_t, verbatim_doc_comment)]
+    percentile_method: PercentileMethod,
+
+    /// Report the distortion, `100 - score`, of every frame instead of its score, and
+    /// compute the statistics over those. --stop-below and --dump-below still compare
+    /// against the score.
+    #[arg(long, verbatim_doc_comment)]
+    invert: bool,
+
+    /// Will output scores for every frame followed by the average at the end.
+    #[arg(long, short)]
+    verbose: bool,
+
+    /// With --verbose, print the scores of this many frames per line instead of one,
+    /// in aligned columns, to scan long runs with less scrolling.
+    #[arg(long, value_name = "N", requires = "verbose", verbatim_doc_comment)]
+    columns: Option<usize>,
+
+    /// Print only `FRAME SCORE` lines to stdout as frames are scored, in completion order,
+    /// and send the summary and everything else to stderr, for shell pipelines.
+    #[arg(long, verbatim_doc_comment)]
+    per_frame_stdout: bool,
+
+    /// Time frame decoding and score computation separately and print the split at the end,
+    /// to tell whether more `--frame-threads` would help.
+    #[arg(long, verbatim_doc_comment)]
+    profile: bool,
+
+    /// Print how many frames each worker thread scored, and how long it was busy versus
+    /// waiting for decoded frames, to see where adding `--frame-threads` stops helping.
+    #[arg(long, verbatim_doc_comment)]
+    thread_stats: bool,
+
+    /// Pin each worker thread to its own core, to keep them off the efficiency cores of
+    /// hybrid CPUs. On Linux, only the performance cores are used if the kernel lists
+    /// them and there is one for every worker; otherwise the workers are spread over
+    /// every core. Where affinity can't be set, the workers are left to the scheduler.
+    #[arg(long, verbatim_doc_comment)]
+    pin_threads: bool,
+
+    /// Show the average of the last N scored frames in the progress bar, instead of the
+    /// mean of all frames so far. The final statistics always cover every frame.
+    #[arg(long, value_name = "N", verbatim_doc_comment)]
+    avg_window: Option<usize>,
+
+    /// Also show the score of the most recently scored frame in the progress bar, to spot
+    /// dips as they happen without the output of `--verbose`.
+    #[arg(long, verbatim_doc_comment)]
+    show_current: bool,
+
+    /// Also score every frame with the chroma removed, and report those statistics
+    /// next to the full scores. A luma-only mean well above the full mean points at
+    /// chroma degradation.
+    #[arg(long, verbatim_doc_comment)]
+    plane_stats: bool,
+
+    /// For PQ or HLG sources, also report a highlight-weighted mean, where every frame
+    /// counts in proportion to how bright its highlights are relative to reference white
+    /// (203 nits). Frames without highlights count once, so a weighted mean well below
+    /// the plain mean points at errors in bright scenes that the plain mean under-reports.
+    #[arg(long, verbatim_doc_comment)]
+    hdr_stats: bool,
+
+    /// Resize the distorted video to the resolution of the source when they differ,
+    /// instead of failing.
+    #[arg(long, verbatim_doc_comment)]
+    resize: bool,
+
+    /// Correct for the sample aspect ratio the inputs declare, by resizing both to the
+    /// display resolution of the source before scoring, so that e.g. an anamorphic
+    /// source and a square pixel encode of it aren't penalized for their different
+    /// storage resolutions. Inputs that declare no SAR have square pixels.
+    #[arg(long, conflicts_with_all = ["mask", "luma_window"], verbatim_doc_comment)]
+    correct_sar: bool,
+
+    /// Don't warn when the inputs have a different number of frames, e.g. when the
+    /// distorted video is a trimmed encode. Frames are still compared by index.
+    #[arg(long, verbatim_doc_comment)]
+    allow_count_mismatch: bool,
+
+    /// Downscale both inputs before scoring, by a factor (e.g. 2) or to WIDTHxHEIGHT.
+    /// Faster, but the scores are not comparable to scores at the native resolution.
+    #[arg(long, value_parser = parse_downscale, verbatim_doc_comment)]
+    downscale: Option<Downscale>,
+
+    /// Score only edges and fine detail, by high-pass filtering both inputs with a
+    /// Laplacian before scoring, to tell blur apart from other artifacts. This is a
+    /// non-standard score that is not comparable to regular SSIMULACRA2 scores.
+    #[arg(long, verbatim_doc_comment)]
+    edges: bool,
+
+    /// Only score every Nth pixel of each row, in a diagonal pattern, for a rough score
+    /// about N times faster. This changes what the metric sees, so the result is not a
+    /// standard SSIMULACRA2 score.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(2..),
+        verbatim_doc_comment
+    )]
+    pixel_stride: Option<u16>,
+
+    /// Whether frames are compared in linear light, as SSIMULACRA2 is defined, or gamma
+    /// encoded (a 1/2.2 power), which weighs differences in dark areas more and in bright
+    /// areas less. Anything but linear is a non-standard score.
+    #[arg(long, value_enum, default_value_t, verbatim_doc_comment)]
+    difference_space: DifferenceSpace,
+
+    /// Clip both inputs to the gamut of these color primaries (e.g. bt709, bt2020) before
+    /// scoring, so that a gamut conversion between source and distorted isn't counted as
+    /// distortion. The score then measures fidelity after the conversion, not the raw
+    /// error of the encode.
+    #[arg(long, value_name = "PRIMARIES", verbatim_doc_comment)]
+    normalize_matrix: Option<String>,
+
+    /// Only score the region of interest given by this mask image (alpha, or luma
+    /// if it has no alpha). Masked-out pixels are made to match the source.
+    /// Must have the resolution of the source.
+    #[arg(long, value_hint = clap::ValueHint::FilePath, verbatim_doc_comment)]
+    mask: Option<PathBuf>,
+
+    /// Only score pixels whose source luma is within MIN-MAX, as fractions from black (0)
+    /// to white (1), e.g. 0-0.2 to check shadow detail for banding. Pixels outside the
+    /// window are made to match the source, like with `--mask`. This is a non-standard,
+    /// targeted score that is not comparable to regular SSIMULACRA2 scores.
+    #[arg(long, value_name = "MIN-MAX", value_parser = parse_luma_window, verbatim_doc_comment)]
+    luma_window: Option<LumaWindow>,
+
+    /// Stop comparing as soon as any frame scores below this value,
+    /// and report the first frame that did.
+    #[arg(long, verbatim_doc_comment)]
+    stop_below: Option<f64>,
+
+    /// Stop decoding new frames after this many seconds, and summarize the frames
+    /// scored so far.
+    #[arg(long, value_name = "SECONDS", verbatim_doc_comment)]
+    time_limit: Option<f64>,
+
+    /// Score this many frames first without counting them towards the statistics,
+    /// the progress bar's fps or `--profile`, so that index building and cache warmup
+    /// don't skew the timings.
+    #[arg(long, default_value_t = 0, verbatim_doc_comment)]
+    warmup: usize,
+
+    /// Write the source and distorted frames of every frame scoring below `--dump-below`
+    /// into this directory as PNGs, for debugging.
+    #[arg(
+        long,
+        group = "dump_output",
+        requires = "dump_below",
+        value_hint = clap::ValueHint::DirPath,
+        verbatim_doc_comment
+    )]
+    dump_frames: Option<PathBuf>,
+
+    /// Write a heatmap of where each frame scoring below `--dump-below` differs from the
+    /// source into this directory as PNGs, from black (identical) through red and yellow
+    /// to white. This is the absolute difference in linear light, a proxy for where the
+    /// SSIMULACRA2 error concentrates.
+    #[arg(
+        long,
+        group = "dump_output",
+        requires = "dump_below",
+        value_hint = clap::ValueHint::DirPath,
+        verbatim_doc_comment
+    )]
+    heatmap: Option<PathBuf>,
+
+    /// Score threshold for `--dump-frames` and `--heatmap`.
+    #[arg(long, requires = "dump_output")]
+    dump_below: Option<f64>,
+
+    /// Write the distorted video to this y4m file, with each frame's score drawn in the top
+    /// left corner. Only the scored frames are written.
+    #[arg(long, value_hint = clap::ValueHint::FilePath, verbatim_doc_comment)]
+    overlay_output: Option<PathBuf>,
+
+    /// Stream one JSON object per frame to this file as frames are scored, e.g.
+    /// {"schema_version":1,"frame":12,"score":87.5}. Lines are in completion order.
+    /// With --invert, the value is named "distortion" instead of "score".
+    /// Use `-` for stdout.
+    /// Paths ending in .gz or .zst are compressed with gzip or zstd.
+    #[arg(long, value_hint = clap::ValueHint::FilePath, verbatim_doc_comment)]
+    jsonl: Option<PathBuf>,
+
+    /// Place every generated file in this directory, along with a `manifest.json`
+    /// listing the inputs, the resolved configuration and the files produced.
+    /// A relative `--dump-frames` directory is created inside it.
+    #[arg(long, value_hint = clap::ValueHint::DirPath, verbatim_doc_comment)]
+    out_dir: Option<PathBuf>,
+
+    /// Append the summary of this run to a CSV file, with the time, the input names and
+    /// whether --invert was given, to keep a history of results across runs. The file
+    /// and its header line are created on the first run.
+    #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath, verbatim_doc_comment)]
+    append_summary: Option<PathBuf>,
+
+    /// Source color matrix. `ictcp` decodes BT.2100 ICtCp to linear RGB before scoring,
+    /// which is experimental.
+    #[arg(long, verbatim_doc_comment)]
+    src_matrix: Option<String>,
+
+    /// Source transfer characteristics
+    #[arg(long)]
+    src_transfer: Option<String>,
+
+    /// Source color primaries
+    #[arg(long)]
+    src_primaries: Option<String>,
+
+    /// Source chroma subsampling (420, 422, 444 or 400), overriding the detected one. The
+    /// chroma planes are resampled to it before scoring.
+    #[arg(long)]
+    src_subsampling: Option<String>,
+
+    /// Range of the source data. `auto` reads it from the input's frame properties or y4m
+    /// header, and assumes limited if it isn't declared. An explicit range overrides a
+    /// declared one, e.g. when the input is mis-tagged. The manifest records which
+    /// of the three decided the range.
+    #[arg(long, value_enum, default_value_t, verbatim_doc_comment)]
+    src_range: ColorRange,
+
+    /// Same as `--src-range full`
+    #[arg(long, conflicts_with = "src_range")]
+    src_full_range: bool,
+
+    /// Distorted color matrix. `ictcp` decodes BT.2100 ICtCp to linear RGB before
+    /// scoring, which is experimental.
+    #[arg(long, verbatim_doc_comment)]
+    dst_matrix: Option<String>,
+
+    /// Distorted transfer characteristics
+    #[arg(long)]
+    dst_transfer: Option<String>,
+
+    /// Distorted color primaries
+    #[arg(long)]
+    dst_primaries: Option<String>,
+
+    /// Distorted chroma subsampling (420, 422, 444 or 400), overriding the detected one.
+    /// The chroma planes are resampled to it before scoring.
+    #[arg(long)]
+    dst_subsampling: Option<String>,
+
+    /// Range of the distorted data. `auto` reads it from the input's frame properties or y4m
+    /// header, and assumes limited if it isn't declared. An explicit range overrides a
+    /// declared one, e.g. when the input is mis-tagged. The manifest records which
+    /// of the three decided the range.
+    #[arg(long, value_enum, default_value_t, verbatim_doc_comment)]
+    dst_range: ColorRange,
+
+    /// Same as `--dst-range full`
+    #[arg(long, conflicts_with = "dst_range")]
+    dst_full_range: bool,
+
+    /// Never guess color properties that neither the command line nor the inputs declare.
+    /// By default, the matrix and primaries are guessed from the resolution, the transfer
+    /// is assumed to be BT.1886 and the range limited. With this, any of them left
+    /// unspecified is an error instead.
+    #[arg(long, verbatim_doc_comment)]
+    no_guess: bool,
+
+    /// Print this command with every color property spelled out, including the ones
+    /// read from the inputs or guessed, to reproduce the run exactly, e.g. in a bug
+    /// report. The colors are given as their numeric codes.
+    #[arg(long, verbatim_doc_comment)]
+    print_command: bool,
+}
+
+impl Commands {
+    /// Exchanges the source and distorted inputs, for `--swap`
+    fn swap_inputs(&mut self) {
+        match self {
+            // With --split, the other half is taken as the reference instead
+            Commands::Image {
+                source,
+                distorted: Some(distorted),
+                ..
+            } => std::mem::swap(source, distorted),
+            Commands::Image {
+                distorted: None, ..
+            } => (),
+            #[cfg(feature = "video")]
+            Commands::Sequence {
+                source, distorted, ..
+            } => std::mem::swap(source, distorted),
+            #[cfg(feature = "video")]
+            Commands::Video(args) => args.swap_inputs(),
+            // Jobs are only read once the batch runs, and swapped then
+            #[cfg(feature = "video")]
+            Commands::Batch { .. } => (),
+            #[cfg(feature = "video")]
+            Commands::DecodeBench { .. } => (),
+            Commands::Selftest => (),
+        }
+    }
+}
+
+#[cfg(feature = "video")]
+impl VideoArgs {
+    fn swap_inputs(&mut self) {
+        use std::mem::swap;
+
+        // With --self-temporal both sides are the same input
+        let Some(distorted) = &mut self.distorted else {
+            return;
+        };
+        swap(&mut self.source, distorted);
+        swap(&mut self.src_matrix, &mut self.dst_matrix);
+        swap(&mut self.src_transfer, &mut self.dst_transfer);
+        swap(&mut self.src_primaries, &mut self.dst_primaries);
+        swap(&mut self.src_subsampling, &mut self.dst_subsampling);
+        swap(&mut self.src_range, &mut self.dst_range);
+        swap(&mut self.src_full_range, &mut self.dst_full_range);
+    }
 }
 
 fn main() {
-    match Cli::parse().command {
-        Commands::Image { source, distorted } => compare_images(&source, &distorted),
+    let mut cli = Cli::parse();
+    #[cfg(feature = "tracing")]
+    diagnostics::init_subscriber();
+    if cli.swap {
+        cli.command.swap_inputs();
+    }
+    match cli.command {
+        Commands::Image {
+            source,
+            distorted,
+            split,
+            mask,
+            also,
+            pass_threshold,
+            invert,
+            pixel_stride,
+        } => {
+            // Kept until the comparison is done, which removes the downloaded files
+            let source_download = download_image(&source).unwrap_or_else(|err| {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            });
+            let source = source_download
+                .as_ref()
+                .map_or(source, |download| download.path().to_path_buf());
+            let (score, classic) = match (distorted, split) {
+                (Some(distorted), _) => {
+                    let distorted_download = download_image(&distorted).unwrap_or_else(|err| {
+                        eprintln!("Error: {err}");
+                        std::process::exit(1);
+                    });
+                    let distorted = distorted_download
+                        .as_ref()
+                        .map_or(distorted, |download| download.path().to_path_buf());
+                    if let Some(path) = same_input(&source, &distorted) {
+                        if cli.strict {
+                            eprintln!(
+                                "Error: Source and distorted are the same file: {}",
+                                path.display()
+                            );
+                            // Exiting skips destructors, which remove the downloaded files
+                            drop(distorted_download);
+                            drop(source_download);
+                            std::process::exit(1);
+                        }
+                        warn_same_input(&path);
+                    }
+                    compare_images(&source, &distorted, mask.as_deref(), &also, pixel_stride)
+                }
+                (None, Some(split)) => {
+                    println!("Reference: {} half", split.reference_half(cli.swap));
+                    compare_split_image(
+                        &source,
+                        split,
+                        cli.swap,
+                        mask.as_deref(),
+                        &also,
+                        pixel_stride,
+                    )
+                }
+                (None, None) => unreachable!("the distorted image is required without --split"),
+            };
+            // Removed before a FAIL exits without running destructors
+            drop(source_download);
+            if invert {
+                println!("Distortion: {:.8}", 100.0 - score);
+            } else {
+                println!("Score: {score:.8}");
+            }
+            for (metric, value) in classic {
+                println!("{}: {}", metric.label(), metric.format(value));
+            }
+            if let Some(threshold) = pass_threshold {
+                if score >= threshold {
+                    println!("PASS (threshold {threshold})");
+                } else {
+                    println!("FAIL (threshold {threshold})");
+                    std::process::exit(1);
+                }
+            }
+        }
         #[cfg(feature = "video")]
-        Commands::Video {
+        Commands::Sequence {
             source,
             distorted,
-            frame_threads,
-            skip_frames,
+            start,
             frames,
-            increment,
-            graph,
+            frame_threads,
             verbose,
-            src_matrix,
-            src_transfer,
-            src_primaries,
-            src_full_range,
-            dst_matrix,
-            dst_transfer,
-            dst_primaries,
-            dst_full_range,
+            keep_going,
         } => {
-            let frame_threads = frame_threads.unwrap_or(1).max(1);
-            let inc = increment.unwrap_or(1).max(1);
-            let src_matrix = src_matrix
-                .map(|i| parse_matrix(&i))
-                .unwrap_or(MatrixCoefficients::Unspecified);
-            let src_transfer = src_transfer
-                .map(|i| parse_transfer(&i))
-                .unwrap_or(TransferCharacteristic::Unspecified);
-            let src_primaries = src_primaries
-                .map(|i| parse_primaries(&i))
-                .unwrap_or(ColorPrimaries::Unspecified);
-            let dst_matrix = dst_matrix
-                .map(|i| parse_matrix(&i))
-                .unwrap_or(MatrixCoefficients::Unspecified);
-            let dst_transfer = dst_transfer
-                .map(|i| parse_transfer(&i))
-                .unwrap_or(TransferCharacteristic::Unspecified);
-            let dst_primaries = dst_primaries
-                .map(|i| parse_primaries(&i))
-                .unwrap_or(ColorPrimaries::Unspecified);
-            compare_videos(
+            match compare_sequences(
                 &source,
                 &distorted,
-                frame_threads,
-                skip_frames,
+                start,
                 frames,
-                inc,
-                graph,
+                frame_threads.max(1),
                 verbose,
-                src_matrix,
-                src_transfer,
-                src_primaries,
-                src_full_range,
-                dst_matrix,
-                dst_transfer,
-                dst_primaries,
-                dst_full_range,
-            )
+                keep_going,
+            ) {
+                Ok((summary, failures)) => {
+                    summary.print("Sequence", "Score");
+                    if !failures.is_empty() {
+                        println!();
+                        println!("Failed image(s):");
+                        for (index, err) in &failures {
+                            println!("{index}: {err}");
+                        }
+                        let err = Ssimu2Error::SequenceFailures {
+                            failed: failures.len(),
+                            total: summary.frames + failures.len(),
+                        };
+                        eprintln!("Error: {err}");
+                        std::process::exit(1);
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Error: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        #[cfg(feature = "video")]
+        Commands::Video(args) => {
+            if let Err(err) = run_video(args, cli.strict) {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "video")]
+        Commands::Batch {
+            jobs,
+            frame_threads,
+            keep_going,
+        } => {
+            if let Err(err) = run_batch(
+                &jobs,
+                frame_threads.max(1),
+                keep_going,
+                cli.strict,
+                cli.swap,
+            ) {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "video")]
+        Commands::DecodeBench {
+            input,
+            frames,
+            raw_format,
+        } => {
+            if let Err(err) = decode_bench(&input, raw_format.map(PipedFormat::Raw), frames) {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            }
         }
+        Commands::Selftest => {
+            if let Err(err) = selftest::run() {
+                eprintln!("Self-test failed: {err}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "video")]
+fn run_video(args: VideoArgs, strict: bool) -> Result<(), Ssimu2Error> {
+    let distorted = args
+        .distorted
+        .clone()
+        .unwrap_or_else(|| args.source.clone());
+    let table = args.table;
+    let summary = score_video(args, strict)?;
+    if table {
+        print_table(&[(&distorted, Some(summary))]);
     }
+    Ok(())
+}
+
+/// The arguments of a single `batch` job, which are those of the `video` command
+#[cfg(feature = "video")]
+#[derive(Parser, Debug)]
+struct BatchJob {
+    #[command(flatten)]
+    video: VideoArgs,
 }
 
-fn compare_images(source: &Path, distorted: &Path) {
-    // For now just assumes the input is sRGB. Trying to keep this as simple as possible for now.
+#[cfg(feature = "video")]
+fn run_batch(
+    jobs: &Path,
+    frame_threads: usize,
+    keep_going: bool,
+    strict: bool,
+    swap: bool,
+) -> Result<(), Ssimu2Error> {
+    let jobs = read_jobs(jobs)?;
+    let mut rows = Vec::with_capacity(jobs.len());
+    let mut failures = Vec::new();
+    for (i, job) in jobs.iter().enumerate() {
+        println!(
+            "Job {}/{}: {} vs {}",
+            i + 1,
+            jobs.len(),
+            job.source,
+            job.distorted
+        );
+        // Parsed like the video command, so every job gets its defaults
+        let frame_threads = frame_threads.to_string();
+        let result = BatchJob::try_parse_from([
+            "batch",
+            "--table",
+            "--frame-threads",
+            &frame_threads,
+            "--",
+            &job.source,
+            &job.distorted,
+        ])
+        .map_err(|e| Ssimu2Error::DecodeError {
+            input: job.distorted.clone(),
+            reason: e.to_string(),
+        })
+        .and_then(|job| {
+            let mut args = job.video;
+            if swap {
+                args.swap_inputs();
+            }
+            score_video(args, strict)
+        });
+        match result {
+            Ok(summary) => {
+                println!("Mean: {:.8}", summary.mean);
+                rows.push((job.distorted.as_str(), Some(summary)));
+            }
+            Err(err) if keep_going => {
+                eprintln!("Error: {err}");
+                // Recorded as a failed row, so the table still lists every job
+                rows.push((job.distorted.as_str(), None));
+                failures.push((job, err));
+            }
+            Err(err) => {
+                // The jobs that already finished are still worth having
+                println!();
+                print_batch_summary(&rows);
+                return Err(err);
+            }
+        }
+        println!();
+        if interrupted() {
+            break;
+        }
+    }
+
+    print_batch_summary(&rows);
+
+    if !failures.is_empty() {
+        println!();
+        println!("Failed job(s):");
+        for (job, err) in &failures {
+            println!("{} vs {}: {err}", job.source, job.distorted);
+        }
+        return Err(Ssimu2Error::BatchFailures {
+            failed: failures.len(),
+            total: rows.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Prints the table of the finished batch jobs and the aggregate score of those that
+/// succeeded
+#[cfg(feature = "video")]
+fn print_batch_summary(rows: &[(&str, Option<Summary>)]) {
+    if rows.is_empty() {
+        return;
+    }
+    print_table(rows);
+    let scored: Vec<&Summary> = rows
+        .iter()
+        .filter_map(|(_, summary)| summary.as_ref())
+        .collect();
+    if scored.is_empty() {
+        return;
+    }
+    let frames: usize = scored.iter().map(|summary| summary.frames).sum();
+    let total: f64 = scored
+        .iter()
+        .map(|summary| summary.mean * summary.frames as f64)
+        .sum();
+    println!();
+    println!(
+        "Aggregate over {} job(s), {frames} frames: mean {:.8}",
+        scored.len(),
+        total / frames as f64
+    );
+}
+
+/// Runs a video comparison and prints its results, except for the `--table` row
+#[cfg(feature = "video")]
+fn score_video(args: VideoArgs, strict: bool) -> Result<Summary, Ssimu2Error> {
+    let VideoArgs {
+        source,
+        distorted,
+        frame_threads,
+        max_memory,
+        buffer_frames,
+        raw_format,
+        stdin_format,
+        align_keyframes,
+        auto_align,
+        self_temporal,
+        verify_reference,
+        fps,
+        skip_frames,
+        frames,
+        increment,
+        include_scene_changes,
+        random_sample,
+        iframes_only,
+        seed,
+        at,
+        range,
+        frame_list,
+        exclude_frames,
+        graph,
+        graph_size,
+        graph_backend,
+        graph_type,
+        graph_color,
+        graph_output,
+        histogram,
+        scenes,
+        table,
+        oneline,
+        percentile_method,
+        invert,
+        verbose,
+        columns,
+        profile,
+        thread_stats,
+        pin_threads,
+        avg_window,
+        show_current,
+        per_frame_stdout,
+        emit,
+        plane_stats,
+        hdr_stats,
+        resize,
+        correct_sar,
+        allow_count_mismatch,
+        downscale,
+        edges,
+        pixel_stride,
+        difference_space,
+        normalize_matrix,
+        mask,
+        luma_window,
+        stop_below,
+        time_limit,
+        warmup,
+        dump_frames,
+        heatmap,
+        dump_below,
+        overlay_output,
+        jsonl,
+        out_dir,
+        append_summary,
+        src_matrix,
+        src_transfer,
+        src_primaries,
+        src_subsampling,
+        src_range,
+        src_full_range,
+        dst_matrix,
+        dst_transfer,
+        dst_primaries,
+        dst_subsampling,
+        dst_range,
+        dst_full_range,
+        no_guess,
+        print_command,
+    } = args;
+
+    // Only one output can have stdout to itself
+    let stdout = Path::new("-");
+    let stdout_outputs: Vec<&'static str> = [
+        (per_frame_stdout, "--per-frame-stdout"),
+        (graph_output.as_deref() == Some(stdout), "--graph-output -"),
+        (jsonl.as_deref() == Some(stdout), "--jsonl -"),
+    ]
+    .into_iter()
+    .filter_map(|(used, option)| used.then_some(option))
+    .collect();
+    if let [first, second, ..] = stdout_outputs[..] {
+        return Err(Ssimu2Error::StdoutConflict { first, second });
+    }
+    // Keeps stdout for the per-frame scores or the graph
+    if per_frame_stdout || graph_output.as_deref() == Some(stdout) {
+        diagnostics::report_to_stderr();
+    }
+    let frame_threads = frame_threads.unwrap_or(1).max(1);
+    let inc = increment.unwrap_or(1).max(1);
+    let src_matrix = src_matrix
+        .map(|i| parse_matrix(&i))
+        .transpose()?
+        .unwrap_or(MatrixCoefficients::Unspecified);
+    let src_transfer = src_transfer
+        .map(|i| parse_transfer(&i))
+        .transpose()?
+        .unwrap_or(TransferCharacteristic::Unspecified);
+    let src_primaries = src_primaries
+        .map(|i| parse_primaries(&i))
+        .transpose()?
+        .unwrap_or(ColorPrimaries::Unspecified);
+    let dst_matrix = dst_matrix
+        .map(|i| parse_matrix(&i))
+        .transpose()?
+        .unwrap_or(MatrixCoefficients::Unspecified);
+    let dst_transfer = dst_transfer
+        .map(|i| parse_transfer(&i))
+        .transpose()?
+        .unwrap_or(TransferCharacteristic::Unspecified);
+    let dst_primaries = dst_primaries
+        .map(|i| parse_primaries(&i))
+        .transpose()?
+        .unwrap_or(ColorPrimaries::Unspecified);
+    let normalize_matrix = normalize_matrix.map(|i| parse_primaries(&i)).transpose()?;
+    let src_subsampling = src_subsampling.map(|i| parse_subsampling(&i)).transpose()?;
+    let dst_subsampling = dst_subsampling.map(|i| parse_subsampling(&i)).transpose()?;
+    let source_color = InputColor {
+        matrix: src_matrix,
+        transfer: src_transfer,
+        primaries: src_primaries,
+        subsampling: src_subsampling,
+        range: if src_full_range {
+            ColorRange::Full
+        } else {
+            src_range
+        },
+    };
+    // The second copy of the source is decoded with the same color settings
+    let (distorted, distorted_color) = match distorted {
+        Some(distorted) => (
+            distorted,
+            InputColor {
+                matrix: dst_matrix,
+                transfer: dst_transfer,
+                primaries: dst_primaries,
+                subsampling: dst_subsampling,
+                range: if dst_full_range {
+                    ColorRange::Full
+                } else {
+                    dst_range
+                },
+            },
+        ),
+        None => {
+            if is_piped(&source) {
+                return Err(Ssimu2Error::DecodeError {
+                    input: source,
+                    reason: "--self-temporal decodes the source twice, so it can't be piped"
+                        .to_string(),
+                });
+            }
+            (source.clone(), source_color)
+        }
+    };
+    let frame_list = frame_list
+        .map(|list| read_frame_list(&list, is_piped(&source) || is_piped(&distorted)))
+        .transpose()?;
+    if is_url(&source) && source == distorted && !self_temporal {
+        if strict {
+            return Err(Ssimu2Error::SameInput { path: source });
+        }
+        warn_same_input(Path::new(&source));
+    }
+    // The history records the inputs as they were given, not where they were downloaded to
+    let names = (source.clone(), distorted.clone());
+    // Kept until the comparison is done, which removes the downloaded files
+    let source_download = download_input(&source)?;
+    let distorted_download = if self_temporal {
+        None
+    } else {
+        download_input(&distorted)?
+    };
+    let source = source_download.as_ref().map_or(source, |download| {
+        download.path().to_string_lossy().into_owned()
+    });
+    let distorted = if self_temporal {
+        source.clone()
+    } else {
+        distorted_download.as_ref().map_or(distorted, |download| {
+            download.path().to_string_lossy().into_owned()
+        })
+    };
+    if let Some(path) =
+        same_input(Path::new(&source), Path::new(&distorted)).filter(|_| !self_temporal)
+    {
+        if strict {
+            return Err(Ssimu2Error::SameInput {
+                path: path.display().to_string(),
+            });
+        }
+        warn_same_input(&path);
+    }
+
+    let options = CompareOptions {
+        frame_threads,
+        max_memory,
+        buffer_frames,
+        piped_format: raw_format
+            .map(PipedFormat::Raw)
+            .or(stdin_format.map(PipedFormat::Y4m)),
+        align_keyframes,
+        auto_align,
+        self_temporal,
+        verify_reference,
+        fps,
+        selection: FrameSelection {
+            skip_frames,
+            frames_to_compare: frames,
+            inc,
+            include_scene_changes,
+            random_sample,
+            iframes_only,
+            seed,
+            timestamps: at,
+            range,
+            listed: frame_list,
+            exclude: exclude_frames.unwrap_or_default(),
+        },
+        graph,
+        graph_size,
+        graph_backend,
+        graph_type,
+        graph_color,
+        graph_output,
+        histogram,
+        scenes,
+        table,
+        oneline,
+        percentile_method,
+        invert,
+        verbose,
+        columns,
+        profile,
+        thread_stats,
+        pin_threads,
+        avg_window,
+        show_current,
+        per_frame_stdout,
+        emit,
+        plane_stats,
+        hdr_stats,
+        scoring: ScoringOptions {
+            resize,
+            downscale,
+            edges,
+            pixel_stride: pixel_stride.map(usize::from),
+            difference_space,
+            normalize_matrix,
+            luma_window,
+        },
+        correct_sar,
+        allow_count_mismatch,
+        mask,
+        stop_below,
+        time_limit: time_limit.map(Duration::from_secs_f64),
+        warmup,
+        dump: dump_below.map(|below| FrameDump {
+            dir: dump_frames,
+            heatmap,
+            below,
+        }),
+        overlay_output,
+        jsonl,
+        out_dir,
+        source_color,
+        distorted_color,
+        no_guess,
+        print_command,
+    };
+    let summary = compare_videos(&source, &distorted, options)?;
+
+    if let Some(path) = append_summary {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        append_summary_csv(&path, time, &names.0, &names.1, invert, &summary)?;
+    }
+
+    Ok(summary)
+}
+
+/// Downloads an image given as a URL to a temporary file. Image mode is built without
+/// `Ssimu2Error`, so the error is the message `Ssimu2Error::Download` would show.
+fn download_image(input: &Path) -> Result<Option<Download>, String> {
+    let Some(url) = input.to_str().filter(|input| is_url(input)) else {
+        return Ok(None);
+    };
+    Download::fetch(url)
+        .map(Some)
+        .map_err(|reason| format!("Failed to download {url}: {reason}"))
+}
+
+/// Downloads a video given as a URL to a temporary file
+#[cfg(feature = "video")]
+fn download_input(input: &str) -> Result<Option<Download>, Ssimu2Error> {
+    if !is_url(input) {
+        return Ok(None);
+    }
+    let download = Download::fetch(input).map_err(|reason| Ssimu2Error::Download {
+        url: input.to_string(),
+        reason,
+    })?;
+    note!("Downloaded {input} to {}", download.path().display());
+    Ok(Some(download))
+}
+
+/// Returns the canonical path if `source` and `distorted` resolve to the same file.
+/// Piped inputs and paths that don't exist never match.
+fn same_input(source: &Path, distorted: &Path) -> Option<PathBuf> {
+    let source = source.canonicalize().ok()?;
+    let distorted = distorted.canonicalize().ok()?;
+    (source == distorted && source.is_file()).then_some(source)
+}
+
+fn warn_same_input(path: &Path) {
+    warning!(
+        "Source and distorted are the same file ({}), so the score is meaningless",
+        path.display()
+    );
+}
+
+/// How `--split` divides a single image into a source and a distorted half
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Split {
+    /// Left and right halves, side by side
+    Horizontal,
+    /// Top and bottom halves, stacked
+    Vertical,
+}
+
+impl Split {
+    /// Name of the half that is scored against, the first one unless `swap` is set
+    fn reference_half(self, swap: bool) -> &'static str {
+        match (self, swap) {
+            (Split::Horizontal, false) => "left",
+            (Split::Horizontal, true) => "right",
+            (Split::Vertical, false) => "top",
+            (Split::Vertical, true) => "bottom",
+        }
+    }
+}
+
+/// Returns the SSIMULACRA2 score of `distorted` against `source`,
+/// along with the value of every metric in `also`
+fn compare_images(
+    source: &Path,
+    distorted: &Path,
+    mask: Option<&Path>,
+    also: &[ClassicMetric],
+    pixel_stride: Option<u16>,
+) -> (f64, Vec<(ClassicMetric, f64)>) {
+    let source_color = image_color_or_srgb(source);
+    let distorted_color = image_color_or_srgb(distorted);
     let source = image::open(source).expect("Failed to open source file");
     let distorted = image::open(distorted).expect("Failed to open distorted file");
+    score_images(
+        &source,
+        source_color,
+        &distorted,
+        distorted_color,
+        mask,
+        also,
+        pixel_stride,
+    )
+}
 
-    let source_data = source
-        .to_rgb32f()
-        .chunks_exact(3)
-        .map(|chunk| [chunk[0], chunk[1], chunk[2]])
-        .collect::<Vec<_>>();
+/// Scores the second half of the image at `path` against the first, or the first
+/// against the second if `swap` is set, for `--split`
+fn compare_split_image(
+    path: &Path,
+    split: Split,
+    swap: bool,
+    mask: Option<&Path>,
+    also: &[ClassicMetric],
+    pixel_stride: Option<u16>,
+) -> (f64, Vec<(ClassicMetric, f64)>) {
+    let color = image_color_or_srgb(path);
+    let image = image::open(path).expect("Failed to open source file");
+    let (width, height) = (image.width(), image.height());
+    let (first, second) = match split {
+        Split::Horizontal => {
+            assert!(
+                width % 2 == 0,
+                "Image width must be even to split it into left and right halves, but is {width}"
+            );
+            (
+                image.crop_imm(0, 0, width / 2, height),
+                image.crop_imm(width / 2, 0, width / 2, height),
+            )
+        }
+        Split::Vertical => {
+            assert!(
+                height % 2 == 0,
+                "Image height must be even to split it into top and bottom halves, but is {height}"
+            );
+            (
+                image.crop_imm(0, 0, width, height / 2),
+                image.crop_imm(0, height / 2, width, height / 2),
+            )
+        }
+    };
+    let (source, distorted) = if swap {
+        (second, first)
+    } else {
+        (first, second)
+    };
+    score_images(&source, color, &distorted, color, mask, also, pixel_stride)
+}
 
-    let source_data = Rgb::new(
-        source_data,
-        source.width() as usize,
-        source.height() as usize,
-        TransferCharacteristic::SRGB,
-        ColorPrimaries::BT709,
-    )
-    .expect("Failed to process source_data into RGB");
+/// Scores two decoded images, see [`compare_images`]
+fn score_images(
+    source: &DynamicImage,
+    source_color: (TransferCharacteristic, ColorPrimaries),
+    distorted: &DynamicImage,
+    distorted_color: (TransferCharacteristic, ColorPrimaries),
+    mask: Option<&Path>,
+    also: &[ClassicMetric],
+    pixel_stride: Option<u16>,
+) -> (f64, Vec<(ClassicMetric, f64)>) {
+    let source_data =
+        image_to_rgb(source, source_color).expect("Failed to process source_data into RGB");
+    let distorted_data = image_to_rgb(distorted, distorted_color)
+        .expect("Failed to process distorted_data into RGB");
+
+    // Mismatched resolutions are left for scoring to report
+    let (width, height) = (source_data.width(), source_data.height());
+    let classic = if (distorted_data.width(), distorted_data.height()) == (width, height) {
+        also.iter()
+            .map(|&metric| {
+                let value =
+                    metric.compute(source_data.data(), distorted_data.data(), width, height);
+                (metric, value)
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let score = if mask.is_some() || pixel_stride.is_some() {
+        let mut source_data =
+            LinearRgb::try_from(source_data).expect("Failed to linearize source_data");
+        let mut distorted_data =
+            LinearRgb::try_from(distorted_data).expect("Failed to linearize distorted_data");
+        if let Some(mask) = mask {
+            let mask = Mask::open(mask).expect("Failed to open mask file");
+            assert_eq!(
+                mask.dimensions(),
+                (source_data.width(), source_data.height()),
+                "Mask must have the same resolution as the source"
+            );
+            mask.apply(&source_data, &mut distorted_data);
+        }
+        if let Some(stride) = pixel_stride {
+            warning!(
+                "Scoring only one in {stride} pixels of each row (--pixel-stride). This is a \
+                 rough estimate, NOT a standard SSIMULACRA2 score."
+            );
+            let stride = usize::from(stride);
+            source_data = stride_pixels(&source_data, stride);
+            distorted_data = stride_pixels(&distorted_data, stride);
+        }
+        compute_frame_ssimulacra2(source_data, distorted_data)
+    } else {
+        compute_frame_ssimulacra2(source_data, distorted_data)
+    }
+    .expect("Failed to calculate ssimulacra2");
+    (score, classic)
+}
+
+/// Uses the color space declared by the image file, falling back to sRGB.
+fn image_color_or_srgb(path: &Path) -> (TransferCharacteristic, ColorPrimaries) {
+    detect_image_color(path).unwrap_or_else(|| {
+        warning!(
+            "{} does not declare a color space, assuming sRGB",
+            path.display()
+        );
+        (TransferCharacteristic::SRGB, ColorPrimaries::BT709)
+    })
+}
 
-    let distorted_data = distorted
+/// Converts a decoded image into RGB data for scoring. Alpha is dropped, and
+/// grayscale images end up neutral (R = G = B).
+fn image_to_rgb(
+    image: &DynamicImage,
+    (transfer, primaries): (TransferCharacteristic, ColorPrimaries),
+) -> anyhow::Result<Rgb> {
+    let data = image
         .to_rgb32f()
         .chunks_exact(3)
         .map(|chunk| [chunk[0], chunk[1], chunk[2]])
         .collect::<Vec<_>>();
 
-    let distorted_data = Rgb::new(
-        distorted_data,
-        distorted.width() as usize,
-        distorted.height() as usize,
-        TransferCharacteristic::SRGB,
-        ColorPrimaries::BT709,
-    )
-    .expect("Failed to process distorted_data into RGB");
+    Ok(Rgb::new(
+        data,
+        image.width() as usize,
+        image.height() as usize,
+        transfer,
+        primaries,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma, RgbImage, Rgba, RgbaImage};
+
+    use super::*;
+
+    const SRGB: (TransferCharacteristic, ColorPrimaries) =
+        (TransferCharacteristic::SRGB, ColorPrimaries::BT709);
+    const SIZE: u32 = 64;
 
-    let result = compute_frame_ssimulacra2(source_data, distorted_data)
-        .expect("Failed to calculate ssimulacra2");
+    /// A gradient with a checkerboard of hard edges, so that noise is measurable
+    fn gray_pattern() -> GrayImage {
+        GrayImage::from_fn(SIZE, SIZE, |x, y| {
+            let edge = if (x / 8 + y / 8) % 2 == 0 { 40 } else { 0 };
+            Luma([((x * 3 + y * 2) % 200 + edge) as u8])
+        })
+    }
+
+    fn score(source: &DynamicImage, distorted: &DynamicImage) -> f64 {
+        score_images(source, SRGB, distorted, SRGB, None, &[], None).0
+    }
+
+    /// Path for a file written by the test `name`, unique to this test run
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ssimulacra2_test_{}_{name}", std::process::id()))
+    }
+
+    #[test]
+    fn grayscale_scores_like_neutral_rgb() {
+        let source = gray_pattern();
+        let noised = GrayImage::from_fn(SIZE, SIZE, |x, y| {
+            let Luma([value]) = *source.get_pixel(x, y);
+            Luma([value.saturating_add(((x * 7 + y * 13) % 5) as u8)])
+        });
+        let gray_score = score(
+            &DynamicImage::ImageLuma8(source.clone()),
+            &DynamicImage::ImageLuma8(noised.clone()),
+        );
+        assert!(
+            (50.0..100.0).contains(&gray_score),
+            "a slightly noised grayscale image scored {gray_score}"
+        );
+
+        let neutral = |image: &GrayImage| {
+            DynamicImage::ImageRgb8(RgbImage::from_fn(SIZE, SIZE, |x, y| {
+                let Luma([value]) = *image.get_pixel(x, y);
+                image::Rgb([value; 3])
+            }))
+        };
+        assert_eq!(gray_score, score(&neutral(&source), &neutral(&noised)));
+    }
 
-    println!("Score: {result:.8}");
+    #[test]
+    fn bgra_scores_like_rgba() {
+        let rgba = RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8, 255])
+        });
+        let blurred = image::imageops::blur(&rgba, 1.0);
+        let (png, bmp, distorted) = (
+            temp_path("bgra.png"),
+            temp_path("bgra.bmp"),
+            temp_path("bgra_distorted.png"),
+        );
+        rgba.save(&png).unwrap();
+        // A 32-bit BMP stores its pixels as BGRA, bottom row first
+        rgba.save(&bmp).unwrap();
+        blurred.save(&distorted).unwrap();
+
+        let bytes = std::fs::read(&bmp).unwrap();
+        let offset = u32::from_le_bytes(bytes[10..14].try_into().unwrap()) as usize;
+        let Rgba([r, g, b, a]) = *rgba.get_pixel(0, SIZE - 1);
+        let first_pixel = &bytes[offset..offset + 4];
+        let rgba_score = compare_images(&png, &distorted, None, &[], None).0;
+        let bgra_score = compare_images(&bmp, &distorted, None, &[], None).0;
+        let same_score = compare_images(&png, &bmp, None, &[], None).0;
+        for path in [png, bmp, distorted] {
+            let _ = std::fs::remove_file(path);
+        }
+
+        assert_eq!(first_pixel, [b, g, r, a]);
+        assert_eq!(rgba_score, bgra_score);
+        assert!(
+            (same_score - 100.0).abs() < 0.01,
+            "BGRA scored {same_score} against RGBA"
+        );
+    }
+
+    #[test]
+    fn image_scores_against_itself_and_a_compressed_copy() {
+        let gray = gray_pattern();
+        let source = RgbImage::from_fn(SIZE, SIZE, |x, y| {
+            let Luma([value]) = *gray.get_pixel(x, y);
+            image::Rgb([value, (x * 4) as u8, (y * 4) as u8])
+        });
+        let (png, jpeg) = (temp_path("compressed.png"), temp_path("compressed.jpg"));
+        source.save(&png).unwrap();
+        let file = std::fs::File::create(&jpeg).unwrap();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(file, 20)
+            .encode_image(&source)
+            .unwrap();
+
+        let identical = compare_images(&png, &png, None, &[], None).0;
+        let compressed = compare_images(&png, &jpeg, None, &[], None).0;
+        let _ = std::fs::remove_file(png);
+        let _ = std::fs::remove_file(jpeg);
+
+        assert!(
+            (identical - 100.0).abs() < 0.01,
+            "identical image scored {identical}"
+        );
+        assert!(
+            compressed < 90.0,
+            "quality 20 JPEG copy scored {compressed}"
+        );
+    }
 }
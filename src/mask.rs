@@ -0,0 +1,81 @@
+use std::path::Path;
+
+use image::ImageResult;
+use ssimulacra2::LinearRgb;
+
+/// Per-pixel importance weights for `--mask`, between 0 (ignored) and 1 (fully scored).
+///
+/// SSIMULACRA2 has no notion of per-pixel weights, so the mask is applied as a
+/// preprocessing step instead: the distorted frame is blended towards the source
+/// by the inverse of the weight, in linear light. Masked-out pixels then match the
+/// source exactly and contribute no error, while partially weighted pixels keep
+/// a proportional part of their distortion.
+///
+/// Masked-out pixels still count towards the size of the frame, so a small region
+/// of interest scores closer to 100 than the same region cropped out on its own would.
+pub struct Mask {
+    weights: Vec<f32>,
+    width: usize,
+    height: usize,
+}
+
+impl Mask {
+    /// Loads a mask from the alpha channel of an image, or from its luma if it has none
+    pub fn open(path: &Path) -> ImageResult<Self> {
+        let image = image::open(path)?;
+        let weights = if image.color().has_alpha() {
+            image
+                .to_rgba32f()
+                .pixels()
+                .map(|pixel| pixel.0[3])
+                .collect()
+        } else {
+            image.to_luma32f().into_raw()
+        };
+        Ok(Self {
+            weights,
+            width: image.width() as usize,
+            height: image.height() as usize,
+        })
+    }
+
+    /// Builds a mask from weights in row-major order
+    #[cfg(feature = "video")]
+    pub fn from_weights(weights: Vec<f32>, width: usize, height: usize) -> Self {
+        assert_eq!(weights.len(), width * height, "One weight per pixel");
+        Self {
+            weights,
+            width,
+            height,
+        }
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Blends `distorted` towards `source` wherever the mask is below 1.
+    /// The source must have the dimensions of the mask.
+    pub fn apply(&self, source: &LinearRgb, distorted: &mut LinearRgb) {
+        assert_eq!(
+            (source.width(), source.height()),
+            self.dimensions(),
+            "Mask dimensions must match the frame"
+        );
+        // Left alone so that scoring reports the resolution mismatch
+        if (distorted.width(), distorted.height()) != self.dimensions() {
+            return;
+        }
+        for ((dst, src), &weight) in distorted
+            .data_mut()
+            .iter_mut()
+            .zip(source.data())
+            .zip(&self.weights)
+        {
+            let weight = weight.clamp(0.0, 1.0);
+            for (dst, &src) in dst.iter_mut().zip(src) {
+                *dst = src + (*dst - src) * weight;
+            }
+        }
+    }
+}
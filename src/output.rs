@@ -0,0 +1,283 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, LineWriter, Stdout, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+
+use crate::video::Summary;
+
+/// Version of the layout of every JSON output, stored as `schema_version` in each of
+/// them. Bumped whenever a field is removed, renamed or changes meaning; new fields
+/// don't change it. The current layouts are:
+///
+/// - `--emit json`: `summary` with `frames`, `mean`, `median`, `std_dev`, `std_err`,
+///   `ci95` (lower and upper bound), `p5`, `p95` and `min`, and `frames`, a list of
+///   `frame` and `score` objects in frame order
+/// - `--jsonl`: one object per scored frame with `frame` and `score`, in the order the
+///   frames were scored
+/// - `manifest.json`: `build`, `inputs`, `config` and `files`
+///
+/// With `--invert`, the per-frame values of `--emit json` and `--jsonl` are stored under
+/// `distortion` instead of `score`, and the summary statistics are distortions as well.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Output files are first written under a temporary name next to their final path,
+/// and only moved into place once every output of the run has been produced. This way
+/// an interrupted run never leaves behind an incomplete set of outputs.
+#[derive(Default)]
+pub struct StagedOutputs {
+    /// Pairs of (temporary path, final path)
+    staged: Vec<(PathBuf, PathBuf)>,
+}
+
+impl StagedOutputs {
+    /// Registers `path` as an output and returns the temporary path to write it to instead.
+    /// The temporary path keeps the extension, so format detection still works on it.
+    pub fn stage(&mut self, path: &Path) -> PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let temp_name = match path.extension() {
+            Some(ext) => format!(".{stem}.partial.{}", ext.to_string_lossy()),
+            None => format!(".{stem}.partial"),
+        };
+        let temp_path = path.with_file_name(temp_name);
+        self.staged.push((temp_path.clone(), path.to_path_buf()));
+        temp_path
+    }
+
+    /// Moves every staged output into place and returns their final paths.
+    pub fn commit(mut self) -> io::Result<Vec<PathBuf>> {
+        let staged = std::mem::take(&mut self.staged);
+        let mut committed = Vec::with_capacity(staged.len());
+        for (temp_path, path) in staged {
+            fs::rename(&temp_path, &path)?;
+            committed.push(path);
+        }
+        Ok(committed)
+    }
+}
+
+impl Drop for StagedOutputs {
+    fn drop(&mut self) {
+        // Only reached without a commit, e.g. when the run failed part way through
+        for (temp_path, _) in &self.staged {
+            let _ = fs::remove_file(temp_path);
+        }
+    }
+}
+
+/// Collects everything a run produced into a single directory, alongside a
+/// `manifest.json` describing the inputs, the resolved configuration and the files.
+pub struct Manifest {
+    dir: PathBuf,
+    source: String,
+    distorted: String,
+    config: Value,
+    files: Vec<PathBuf>,
+}
+
+impl Manifest {
+    pub fn new(dir: PathBuf, source: &str, distorted: &str) -> Self {
+        Self {
+            dir,
+            source: source.to_string(),
+            distorted: distorted.to_string(),
+            config: Value::Null,
+            files: Vec::new(),
+        }
+    }
+
+    /// The directory every output of the run is placed in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn set_config(&mut self, config: Value) {
+        self.config = config;
+    }
+
+    pub fn add_file(&mut self, path: PathBuf) {
+        self.files.push(path);
+    }
+
+    /// Stages `manifest.json` into `outputs`, so it is committed with the other outputs.
+    pub fn write(&self, outputs: &mut StagedOutputs) -> io::Result<PathBuf> {
+        let path = self.dir.join("manifest.json");
+        let manifest = json!({
+            "schema_version": SCHEMA_VERSION,
+            "build": {
+                "version": env!("CARGO_PKG_VERSION"),
+                "git_hash": env!("SSIMULACRA2_GIT_HASH"),
+                "date": env!("SSIMULACRA2_BUILD_DATE"),
+            },
+            "inputs": {
+                "source": self.source,
+                "distorted": self.distorted,
+            },
+            "config": self.config,
+            "files": self.files,
+        });
+        let file = BufWriter::new(File::create(outputs.stage(&path))?);
+        serde_json::to_writer_pretty(file, &manifest)?;
+        Ok(path)
+    }
+}
+
+/// Result files that `--emit` writes from a single run
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Emit {
+    /// Graph of the scores, the same as `--graph`
+    Png,
+    /// A `frame,score` line for every scored frame, or `frame,distortion` with `--invert`
+    Csv,
+    /// The score of every frame along with the summary statistics
+    Json,
+}
+
+/// Writes a `frame,score` line for every scored frame after a header line, or a
+/// `frame,distortion` line with `invert`
+pub fn write_csv(path: &Path, scores: &[(usize, f64)], invert: bool) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "frame,{}", value_key(invert))?;
+    for (frame, score) in scores {
+        writeln!(file, "{frame},{score:.8}")?;
+    }
+    file.flush()
+}
+
+pub fn write_json(
+    path: &Path,
+    scores: &[(usize, f64)],
+    summary: &Summary,
+    invert: bool,
+) -> io::Result<()> {
+    let key = value_key(invert);
+    let results = json!({
+        "schema_version": SCHEMA_VERSION,
+        "summary": {
+            "frames": summary.frames,
+            "mean": summary.mean,
+            "median": summary.median,
+            "std_dev": summary.std_dev,
+            "std_err": summary.std_err,
+            "ci95": [summary.ci95.0, summary.ci95.1],
+            "p5": summary.p5,
+            "p95": summary.p95,
+            "min": summary.min,
+        },
+        "frames": scores
+            .iter()
+            .map(|&(frame, score)| json!({ "frame": frame, key: score }))
+            .collect::<Vec<_>>(),
+    });
+    let file = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(file, &results)?;
+    Ok(())
+}
+
+/// Name of the per-frame value in CSV and JSON outputs, which are distortions with
+/// `--invert`
+pub const fn value_key(invert: bool) -> &'static str {
+    if invert {
+        "distortion"
+    } else {
+        "score"
+    }
+}
+
+/// Appends a line with the summary of a run to the CSV file at `path`, creating it with
+/// a header line if needed, so that repeated runs build up a history of results. The
+/// time is in seconds since the Unix epoch, and `invert` records whether the statistics
+/// are over distortions (`--invert`) rather than scores.
+pub fn append_summary_csv(
+    path: &Path,
+    time: u64,
+    source: &str,
+    distorted: &str,
+    invert: bool,
+    summary: &Summary,
+) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut lines = String::new();
+    if file.metadata()?.len() == 0 {
+        lines.push_str("time,source,distorted,invert,frames,mean,median,std_dev,p5,p95,min\n");
+    }
+    lines.push_str(&format!(
+        "{time},{},{},{invert},{},{:.8},{:.8},{:.8},{:.8},{:.8},{:.8}\n",
+        csv_field(source),
+        csv_field(distorted),
+        summary.frames,
+        summary.mean,
+        summary.median,
+        summary.std_dev,
+        summary.p5,
+        summary.p95,
+        summary.min
+    ));
+    // Appends are only atomic per write, so concurrent runs can't interleave a line
+    file.write_all(lines.as_bytes())
+}
+
+/// Quotes a CSV field if it contains a separator, a quote or a line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// A stream of per-frame output, compressed if the file name ends in `.gz` or `.zst`.
+/// Uncompressed files are flushed after every line, so they can be followed live.
+pub enum FrameOutput {
+    Plain(LineWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+    Stdout(Stdout),
+}
+
+impl FrameOutput {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = File::create(path)?;
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_ascii_lowercase().to_string_lossy().into_owned());
+        Ok(match extension.as_deref() {
+            Some("gz") => Self::Gzip(GzEncoder::new(BufWriter::new(file), Compression::default())),
+            Some("zst") => Self::Zstd(zstd::Encoder::new(BufWriter::new(file), 0)?),
+            _ => Self::Plain(LineWriter::new(file)),
+        })
+    }
+
+    /// Writes out anything still buffered, including the trailer of compressed streams
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Plain(mut writer) => writer.flush(),
+            Self::Gzip(encoder) => encoder.finish()?.flush(),
+            Self::Zstd(encoder) => encoder.finish()?.flush(),
+            Self::Stdout(mut stdout) => stdout.flush(),
+        }
+    }
+}
+
+impl Write for FrameOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(writer) => writer.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+            Self::Stdout(stdout) => stdout.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+            Self::Stdout(stdout) => stdout.flush(),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use av_metrics_decoders::{ChromaSampling, Frame, Pixel, VideoDetails};
+use plotters::prelude::*;
+
+use crate::error::Ssimu2Error;
+
+/// Writes the distorted frames as a y4m video, with each frame's score drawn
+/// into its top left corner.
+pub struct OverlayWriter {
+    encoder: y4m::Encoder<BufWriter<File>>,
+    details: VideoDetails,
+    /// Scored frames that arrived before an earlier frame, keyed by decoding order
+    pending: BTreeMap<usize, Vec<u8>>,
+    /// Decoding order position of the next frame to write
+    next: usize,
+}
+
+impl OverlayWriter {
+    pub fn create(path: &Path, details: &VideoDetails) -> Result<Self, Ssimu2Error> {
+        let colorspace = match (details.chroma_sampling, details.bit_depth) {
+            (ChromaSampling::Cs400, 8) => y4m::Colorspace::Cmono,
+            (ChromaSampling::Cs400, 12) => y4m::Colorspace::Cmono12,
+            (ChromaSampling::Cs420, 8) => y4m::Colorspace::C420jpeg,
+            (ChromaSampling::Cs420, 10) => y4m::Colorspace::C420p10,
+            (ChromaSampling::Cs420, 12) => y4m::Colorspace::C420p12,
+            (ChromaSampling::Cs422, 8) => y4m::Colorspace::C422,
+            (ChromaSampling::Cs422, 10) => y4m::Colorspace::C422p10,
+            (ChromaSampling::Cs422, 12) => y4m::Colorspace::C422p12,
+            (ChromaSampling::Cs444, 8) => y4m::Colorspace::C444,
+            (ChromaSampling::Cs444, 10) => y4m::Colorspace::C444p10,
+            (ChromaSampling::Cs444, 12) => y4m::Colorspace::C444p12,
+            (chroma_sampling, bit_depth) => {
+                return Err(io::Error::other(format!(
+                    "y4m cannot store {bit_depth}-bit {chroma_sampling:?} video"
+                ))
+                .into())
+            }
+        };
+        // The time base is the duration of a single frame
+        let framerate = y4m::Ratio::new(
+            details.time_base.den as usize,
+            details.time_base.num as usize,
+        );
+        let encoder = y4m::encode(details.width, details.height, framerate)
+            .with_colorspace(colorspace)
+            .write_header(BufWriter::new(File::create(path)?))
+            .map_err(y4m_error)?;
+
+        Ok(Self {
+            encoder,
+            details: *details,
+            pending: BTreeMap::new(),
+            next: 0,
+        })
+    }
+
+    /// Draws the score onto `frame` and writes it once every frame decoded before it
+    /// has been written. `position` is the order the frame was decoded in.
+    pub fn push<T: Pixel>(
+        &mut self,
+        position: usize,
+        score: f64,
+        mut frame: Frame<T>,
+    ) -> Result<(), Ssimu2Error> {
+        self.draw_score(&mut frame, score);
+        self.pending.insert(position, self.pack_planes(&frame));
+
+        while let Some(planes) = self.pending.remove(&self.next) {
+            let (y, rest) = planes.split_at(self.plane_size(0));
+            let (u, v) = rest.split_at(self.plane_size(1));
+            self.encoder
+                .write_frame(&y4m::Frame::new([y, u, v], None))
+                .map_err(y4m_error)?;
+            self.next += 1;
+        }
+        Ok(())
+    }
+
+    /// Dimensions of a plane without padding
+    fn plane_dimensions(&self, plane: usize) -> (usize, usize) {
+        let (width, height) = (self.details.width, self.details.height);
+        match (plane, self.details.chroma_sampling.get_decimation()) {
+            (0, _) => (width, height),
+            (_, Some((ss_x, ss_y))) => ((width + ss_x) >> ss_x, (height + ss_y) >> ss_y),
+            (_, None) => (0, 0),
+        }
+    }
+
+    fn plane_size(&self, plane: usize) -> usize {
+        let (width, height) = self.plane_dimensions(plane);
+        let bytes_per_sample = if self.details.bit_depth > 8 { 2 } else { 1 };
+        width * height * bytes_per_sample
+    }
+
+    /// Copies the visible part of every plane into one buffer, in the sample layout y4m expects
+    fn pack_planes<T: Pixel>(&self, frame: &Frame<T>) -> Vec<u8> {
+        let mut packed = Vec::with_capacity((0..3).map(|p| self.plane_size(p)).sum());
+        for (p, plane) in frame.planes.iter().enumerate() {
+            let (width, height) = self.plane_dimensions(p);
+            for row in plane.rows_iter().take(height) {
+                for &sample in &row[..width] {
+                    let sample: u32 = sample.into();
+                    if self.details.bit_depth > 8 {
+                        packed.extend_from_slice(&(sample as u16).to_le_bytes());
+                    } else {
+                        packed.push(sample as u8);
+                    }
+                }
+            }
+        }
+        packed
+    }
+
+    /// Renders the score as white text on a black box, and copies it into the top left
+    /// corner of the luma plane. The chroma under the box is set to neutral.
+    fn draw_score<T: Pixel>(&self, frame: &mut Frame<T>, score: f64) {
+        let font_size = (self.details.height / 24).max(12) as u32;
+        let box_width = (font_size * 9).min(self.details.width as u32);
+        let box_height = (font_size * 3 / 2).min(self.details.height as u32);
+
+        let mut buffer = vec![0u8; box_width as usize * box_height as usize * 3];
+        {
+            let root = BitMapBackend::with_buffer(&mut buffer, (box_width, box_height))
+                .into_drawing_area();
+            root.fill(&BLACK).unwrap();
+            root.draw_text(
+                &format!("{score:.2}"),
+                &("sans-serif", font_size as f64).into_font().color(&WHITE),
+                (font_size as i32 / 2, font_size as i32 / 4),
+            )
+            .unwrap();
+            root.present().unwrap();
+        }
+
+        let max_value = (1u32 << self.details.bit_depth) - 1;
+        let luma = &mut frame.planes[0];
+        let stride = luma.cfg.stride;
+        let data = luma.data_origin_mut();
+        for y in 0..box_height as usize {
+            for x in 0..box_width as usize {
+                let gray = buffer[(y * box_width as usize + x) * 3] as u32;
+                data[y * stride + x] = T::cast_from((gray * max_value / 255) as u16);
+            }
+        }
+
+        if self.details.chroma_sampling == ChromaSampling::Cs400 {
+            return;
+        }
+        let neutral = T::cast_from(1u16 << (self.details.bit_depth - 1));
+        for chroma in &mut frame.planes[1..] {
+            let (ss_x, ss_y) = (chroma.cfg.xdec, chroma.cfg.ydec);
+            let stride = chroma.cfg.stride;
+            let data = chroma.data_origin_mut();
+            for y in 0..(box_height as usize + ss_y) >> ss_y {
+                for x in 0..(box_width as usize + ss_x) >> ss_x {
+                    data[y * stride + x] = neutral;
+                }
+            }
+        }
+    }
+}
+
+fn y4m_error(err: y4m::Error) -> Ssimu2Error {
+    io::Error::other(format!("{err:?}")).into()
+}
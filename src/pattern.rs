@@ -0,0 +1,182 @@
+use std::str::FromStr;
+
+use av_metrics_decoders::{ChromaSampling, Decoder, Frame, Pixel, VideoDetails};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Prefix of an input name that selects a generated test pattern, e.g. `pattern:gradient`
+pub const PATTERN_PREFIX: &str = "pattern:";
+
+/// BT.709 luma coefficients of red and blue, which patterns are always encoded with
+const KR: f64 = 0.2126;
+const KB: f64 = 0.0722;
+
+/// A test signal generated in memory instead of decoded from a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    /// 75% color bars over the SMPTE castellations and a bottom row with -I, white and +Q
+    SmpteBars,
+    /// Horizontal ramp from black to white
+    Gradient,
+    /// Uniform random samples, different but reproducible for every frame
+    Noise,
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "smpte-bars" | "bars" => Ok(Pattern::SmpteBars),
+            "gradient" => Ok(Pattern::Gradient),
+            "noise" => Ok(Pattern::Noise),
+            _ => Err(format!(
+                "unknown pattern `{name}`, expected smpte-bars, gradient or noise"
+            )),
+        }
+    }
+}
+
+/// Returns the pattern named by `input`, or `None` if it doesn't name one
+pub fn parse_pattern(input: &str) -> Option<Result<Pattern, String>> {
+    input.strip_prefix(PATTERN_PREFIX).map(Pattern::from_str)
+}
+
+/// Generates an endless sequence of `pattern` frames, in the geometry of another input.
+/// Frames are limited range BT.709 YCbCr.
+pub struct PatternDecoder {
+    pattern: Pattern,
+    details: VideoDetails,
+    next_frame: u64,
+}
+
+impl PatternDecoder {
+    pub fn new(pattern: Pattern, details: VideoDetails) -> Self {
+        Self {
+            pattern,
+            details,
+            next_frame: 0,
+        }
+    }
+
+    /// Nonlinear R'G'B' of the pixel at (`x`, `y`), in the 0-1 range
+    fn rgb(&self, x: usize, y: usize) -> [f64; 3] {
+        let (width, height) = (self.details.width, self.details.height);
+        match self.pattern {
+            Pattern::SmpteBars => {
+                // Seven equally wide bars, and the bottom row in multiples of their width
+                let bar = x * 7 / width;
+                let row = y * 12 / height;
+                const BARS: [[f64; 3]; 7] = [
+                    [0.75, 0.75, 0.75],
+                    [0.75, 0.75, 0.0],
+                    [0.0, 0.75, 0.75],
+                    [0.0, 0.75, 0.0],
+                    [0.75, 0.0, 0.75],
+                    [0.75, 0.0, 0.0],
+                    [0.0, 0.0, 0.75],
+                ];
+                const BLACK: [f64; 3] = [0.0; 3];
+                if row < 8 {
+                    BARS[bar]
+                } else if row < 9 {
+                    // Blue, magenta and cyan bars in reverse order between black ones
+                    if bar % 2 == 0 {
+                        BARS[6 - bar]
+                    } else {
+                        BLACK
+                    }
+                } else {
+                    match x * 28 / width {
+                        0..=4 => [0.0, 0.2456, 0.4125],
+                        5..=9 => [1.0, 1.0, 1.0],
+                        10..=14 => [0.2536, 0.0, 0.4219],
+                        _ => BLACK,
+                    }
+                }
+            }
+            Pattern::Gradient => [x as f64 / (width - 1).max(1) as f64; 3],
+            Pattern::Noise => unreachable!("noise is generated directly in YCbCr"),
+        }
+    }
+
+    /// Limited range samples of a pixel, as Y, Cb and Cr
+    fn ycbcr(&self, [r, g, b]: [f64; 3]) -> [u16; 3] {
+        let y = KR * r + (1.0 - KR - KB) * g + KB * b;
+        let cb = (b - y) / (2.0 * (1.0 - KB));
+        let cr = (r - y) / (2.0 * (1.0 - KR));
+        let scale = f64::from(1u32 << (self.details.bit_depth - 8));
+        [
+            ((16.0 + 219.0 * y) * scale).round() as u16,
+            ((128.0 + 224.0 * cb) * scale).round() as u16,
+            ((128.0 + 224.0 * cr) * scale).round() as u16,
+        ]
+    }
+}
+
+impl Decoder for PatternDecoder {
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        let details = &self.details;
+        let bytes = if details.bit_depth > 8 { 2 } else { 1 };
+        let (chroma_width, chroma_height) = details
+            .chroma_sampling
+            .get_chroma_dimensions(details.width, details.height);
+        let (ss_x, ss_y) = details.chroma_sampling.get_decimation().unwrap_or((0, 0));
+
+        let mut planes = [
+            Vec::with_capacity(details.width * details.height * bytes),
+            Vec::with_capacity(chroma_width * chroma_height * bytes),
+            Vec::with_capacity(chroma_width * chroma_height * bytes),
+        ];
+        let push = |plane: &mut Vec<u8>, sample: u16| {
+            if bytes == 2 {
+                plane.extend_from_slice(&sample.to_le_bytes());
+            } else {
+                plane.push(sample as u8);
+            }
+        };
+        if self.pattern == Pattern::Noise {
+            let mut rng = StdRng::seed_from_u64(self.next_frame);
+            let scale = 1u16 << (details.bit_depth - 8);
+            for (plane, (width, height, min, max)) in planes.iter_mut().zip([
+                (details.width, details.height, 16, 235),
+                (chroma_width, chroma_height, 16, 240),
+                (chroma_width, chroma_height, 16, 240),
+            ]) {
+                for _ in 0..width * height {
+                    push(plane, rng.gen_range(min * scale..=max * scale));
+                }
+            }
+        } else {
+            for y in 0..details.height {
+                for x in 0..details.width {
+                    let [luma, cb, cr] = self.ycbcr(self.rgb(x, y));
+                    push(&mut planes[0], luma);
+                    // Chroma is point sampled at the top left luma position it covers
+                    let on_chroma_grid = x % (1 << ss_x) == 0 && y % (1 << ss_y) == 0;
+                    if details.chroma_sampling != ChromaSampling::Cs400 && on_chroma_grid {
+                        push(&mut planes[1], cb);
+                        push(&mut planes[2], cr);
+                    }
+                }
+            }
+        }
+        self.next_frame += 1;
+
+        let mut frame: Frame<T> =
+            Frame::new_with_padding(details.width, details.height, details.chroma_sampling, 0);
+        frame.planes[0].copy_from_raw_u8(&planes[0], details.width * bytes, bytes);
+        if details.chroma_sampling != ChromaSampling::Cs400 {
+            frame.planes[1].copy_from_raw_u8(&planes[1], chroma_width * bytes, bytes);
+            frame.planes[2].copy_from_raw_u8(&planes[2], chroma_width * bytes, bytes);
+        }
+        Some(frame)
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        self.details.bit_depth
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        self.details
+    }
+}
@@ -0,0 +1,257 @@
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+use av_metrics_decoders::{ChromaSampling, Decoder, Frame, Pixel, VideoDetails};
+
+/// How piped input is read, if not as plain y4m
+#[derive(Clone, Copy, Debug)]
+pub enum PipedFormat {
+    /// Headerless planar frames, for `--raw-format`
+    Raw(RawFormat),
+    /// y4m with the pixel format of its header replaced, for `--stdin-format`
+    Y4m(PixelFormat),
+}
+
+/// Chroma subsampling and bit depth of planar samples, given as an ffmpeg pixel format
+/// name: gray, yuv420p, yuv422p or yuv444p, optionally followed by 10le, 12le or 16le
+#[derive(Clone, Copy, Debug)]
+pub struct PixelFormat {
+    pub chroma_sampling: ChromaSampling,
+    pub bit_depth: usize,
+}
+
+impl FromStr for PixelFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let pixfmt = input.trim().to_ascii_lowercase();
+        let (chroma_sampling, depth) = if let Some(depth) = pixfmt.strip_prefix("gray") {
+            (ChromaSampling::Cs400, depth)
+        } else if let Some(depth) = pixfmt.strip_prefix("yuv420p") {
+            (ChromaSampling::Cs420, depth)
+        } else if let Some(depth) = pixfmt.strip_prefix("yuv422p") {
+            (ChromaSampling::Cs422, depth)
+        } else if let Some(depth) = pixfmt.strip_prefix("yuv444p") {
+            (ChromaSampling::Cs444, depth)
+        } else {
+            return Err("unsupported pixel format".to_string());
+        };
+        // High bit depth samples are always read as little endian 16-bit words
+        let bit_depth = match depth {
+            "" => 8,
+            "10le" | "10" => 10,
+            "12le" | "12" => 12,
+            "16le" | "16" => 16,
+            _ => return Err("unsupported bit depth".to_string()),
+        };
+        Ok(Self {
+            chroma_sampling,
+            bit_depth,
+        })
+    }
+}
+
+/// Geometry of a headerless planar stream, given as `WIDTHxHEIGHT:PIXFMT:FPS`,
+/// e.g. `1920x1080:yuv420p10le:24000/1001`
+#[derive(Clone, Copy, Debug)]
+pub struct RawFormat {
+    pub width: usize,
+    pub height: usize,
+    pub bit_depth: usize,
+    pub chroma_sampling: ChromaSampling,
+    /// Frame rate as a numerator and denominator
+    pub fps: (u64, u64),
+}
+
+impl RawFormat {
+    fn bytes_per_sample(&self) -> usize {
+        if self.bit_depth > 8 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Size in bytes of each plane of a single frame
+    fn plane_sizes(&self) -> [usize; 3] {
+        let bytes = self.bytes_per_sample();
+        let (chroma_width, chroma_height) = self
+            .chroma_sampling
+            .get_chroma_dimensions(self.width, self.height);
+        let chroma_size = chroma_width * chroma_height * bytes;
+        [self.width * self.height * bytes, chroma_size, chroma_size]
+    }
+}
+
+impl FromStr for RawFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| {
+            format!("invalid raw format `{input}`: {reason}, expected WIDTHxHEIGHT:PIXFMT:FPS")
+        };
+        let mut parts = input.split(':');
+        let (Some(size), Some(pixfmt), Some(fps), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid("wrong number of fields"));
+        };
+
+        let (width, height) = size
+            .to_ascii_lowercase()
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+            .filter(|&(w, h): &(usize, usize)| w > 0 && h > 0)
+            .ok_or_else(|| invalid("bad size"))?;
+
+        let PixelFormat {
+            chroma_sampling,
+            bit_depth,
+        } = pixfmt.parse().map_err(|reason: String| invalid(&reason))?;
+
+        let fps = match fps.split_once('/') {
+            Some((num, den)) => num.trim().parse().ok().zip(den.trim().parse().ok()),
+            None => fps
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|fps| fps.is_finite())
+                .map(|fps| ((fps * 1000.0).round() as u64, 1000)),
+        }
+        .filter(|&(num, den)| num > 0 && den > 0)
+        .ok_or_else(|| invalid("bad frame rate"))?;
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            chroma_sampling,
+            fps,
+        })
+    }
+}
+
+/// Reads fixed size frames of headerless planar video, as described by a [`RawFormat`]
+pub struct RawDecoder<R: BufRead + Send> {
+    reader: R,
+    format: RawFormat,
+    /// Whether every frame is preceded by a y4m `FRAME` header
+    frame_headers: bool,
+}
+
+impl<R: BufRead + Send> RawDecoder<R> {
+    pub fn new(reader: R, format: RawFormat) -> Self {
+        Self {
+            reader,
+            format,
+            frame_headers: false,
+        }
+    }
+
+    /// Reads a y4m stream, taking the size and frame rate from its header but the pixel
+    /// format from `pixel_format`, for headers with tags the y4m decoder misreads
+    pub fn with_y4m_header(mut reader: R, pixel_format: PixelFormat) -> Result<Self, String> {
+        let mut header = String::new();
+        reader
+            .read_line(&mut header)
+            .map_err(|e| format!("failed to read the y4m header: {e}"))?;
+        let mut params = header.split_ascii_whitespace();
+        if params.next() != Some("YUV4MPEG2") {
+            return Err("input is not y4m".to_string());
+        }
+        let (mut width, mut height, mut fps) = (None, None, None);
+        for param in params {
+            match param.split_at(1) {
+                ("W", value) => width = value.parse().ok(),
+                ("H", value) => height = value.parse().ok(),
+                ("F", value) => {
+                    fps = value
+                        .split_once(':')
+                        .and_then(|(num, den)| Some((num.parse().ok()?, den.parse().ok()?)))
+                }
+                _ => (),
+            }
+        }
+        let (Some(width), Some(height)) = (width, height) else {
+            return Err("the y4m header has no frame size".to_string());
+        };
+        let format = RawFormat {
+            width,
+            height,
+            bit_depth: pixel_format.bit_depth,
+            chroma_sampling: pixel_format.chroma_sampling,
+            // Same default as the y4m decoder
+            fps: fps
+                .filter(|&(num, den)| num > 0 && den > 0)
+                .unwrap_or((25, 1)),
+        };
+        Ok(Self {
+            reader,
+            format,
+            frame_headers: true,
+        })
+    }
+}
+
+impl<R: BufRead + Send> RawDecoder<R> {
+    /// Reads the next frame, or `None` at the end of the stream
+    pub fn read_frame<T: Pixel>(&mut self) -> Result<Option<Frame<T>>, String> {
+        if self.frame_headers {
+            let mut header = Vec::new();
+            match self.reader.read_until(b'\n', &mut header) {
+                Ok(0) => return Ok(None),
+                Ok(_) if header.starts_with(b"FRAME") => (),
+                Ok(_) => return Err("missing y4m frame header".to_string()),
+                Err(e) => return Err(format!("failed to read y4m frame header: {e}")),
+            }
+        }
+
+        let format = &self.format;
+        let sizes = format.plane_sizes();
+        let mut buffer = vec![0u8; sizes.iter().sum()];
+        match self.reader.read_exact(&mut buffer) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(format!("failed to read raw frame: {e}")),
+        }
+
+        let bytes = format.bytes_per_sample();
+        let (chroma_width, _) = format
+            .chroma_sampling
+            .get_chroma_dimensions(format.width, format.height);
+        let mut frame: Frame<T> =
+            Frame::new_with_padding(format.width, format.height, format.chroma_sampling, 0);
+        let (y, rest) = buffer.split_at(sizes[0]);
+        let (u, v) = rest.split_at(sizes[1]);
+        frame.planes[0].copy_from_raw_u8(y, format.width * bytes, bytes);
+        if format.chroma_sampling != ChromaSampling::Cs400 {
+            frame.planes[1].copy_from_raw_u8(u, chroma_width * bytes, bytes);
+            frame.planes[2].copy_from_raw_u8(v, chroma_width * bytes, bytes);
+        }
+        Ok(Some(frame))
+    }
+}
+
+impl<R: BufRead + Send> Decoder for RawDecoder<R> {
+    /// Decoding errors are only reported by [`read_frame`](Self::read_frame)
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        self.read_frame().ok().flatten()
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        self.format.bit_depth
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        let mut details = VideoDetails {
+            width: self.format.width,
+            height: self.format.height,
+            bit_depth: self.format.bit_depth,
+            chroma_sampling: self.format.chroma_sampling,
+            ..VideoDetails::default()
+        };
+        // The time base is the duration of a single frame
+        (details.time_base.num, details.time_base.den) = (self.format.fps.1, self.format.fps.0);
+        details
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// How many random names are tried for the temporary file before giving up
+const TEMP_FILE_ATTEMPTS: usize = 16;
+
+/// Whether `input` is an `http://` or `https://` URL, which is downloaded before decoding
+pub fn is_url(input: &str) -> bool {
+    let input = input.to_ascii_lowercase();
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// A remote input downloaded to a temporary file, which is removed again when dropped
+pub struct Download {
+    path: PathBuf,
+}
+
+impl Download {
+    /// Downloads `url` with `curl`, which has to be installed. The temporary file keeps
+    /// the extension of the URL, so that the input is decoded the same way as a local
+    /// file with that name.
+    pub fn fetch(url: &str) -> Result<Self, String> {
+        // Only the path of the URL has an extension, not the host, query or fragment
+        let url_path = url
+            .split(['?', '#'])
+            .next()
+            .and_then(|url| url.split_once("://"))
+            .and_then(|(_, rest)| rest.split_once('/'))
+            .map_or("", |(_, path)| path);
+        let extension = Path::new(url_path)
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        // Created before downloading, so that a partial download is removed as well
+        let (download, file) = Self::create(&extension)
+            .map_err(|e| format!("failed to create a temporary file: {e}"))?;
+
+        // curl writes into the file that was just created instead of opening the path
+        // itself, which another user could have replaced in a shared temporary directory
+        let output = Command::new("curl")
+            .args(["--fail", "--silent", "--show-error", "--location"])
+            .arg(url)
+            .stdout(Stdio::from(file))
+            .output()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => {
+                    "curl is needed to download URLs, but it isn't installed".to_string()
+                }
+                _ => format!("could not run curl: {e}"),
+            })?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(match stderr.trim() {
+                "" => format!("curl exited with {}", output.status),
+                reason => reason.to_string(),
+            });
+        }
+        Ok(download)
+    }
+
+    /// Creates a new temporary file with a random name, failing instead of opening a
+    /// file or symlink that already exists under that name
+    fn create(extension: &str) -> io::Result<(Self, File)> {
+        let mut error = None;
+        for _ in 0..TEMP_FILE_ATTEMPTS {
+            let name = RandomState::new().build_hasher().finish();
+            let path = env::temp_dir().join(format!("ssimulacra2_download_{name:016x}{extension}"));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((Self { path }, file)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => error = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(error.expect("At least one name was tried"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
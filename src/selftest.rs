@@ -0,0 +1,279 @@
+use ssimulacra2::{compute_frame_ssimulacra2, ColorPrimaries, Rgb, TransferCharacteristic};
+
+use crate::compare_images;
+
+const SIZE: usize = 128;
+/// Range a lightly blurred gradient is expected to score in. It's kept loose so that
+/// only a broken build, not floating point differences between platforms, fails it.
+const BLURRED_RANGE: (f64, f64) = (30.0, 70.0);
+
+/// Runs every check in turn, printing each result, and stops at the first failure
+pub fn run() -> Result<(), String> {
+    let source = gradient();
+    let blurred = box_blur(&source);
+
+    let score = score_frames(&source, &source)?;
+    if (score - 100.0).abs() > 0.01 {
+        return Err(format!("identical frames scored {score:.8}, expected 100"));
+    }
+    println!("Identical frames: ok ({score:.8})");
+
+    let score = score_frames(&source, &blurred)?;
+    if !(BLURRED_RANGE.0..=BLURRED_RANGE.1).contains(&score) {
+        return Err(format!(
+            "blurred frame scored {score:.8}, expected between {} and {}",
+            BLURRED_RANGE.0, BLURRED_RANGE.1
+        ));
+    }
+    println!("Blurred frame: ok ({score:.8})");
+
+    let (identical, compressed) = image_file_scores(&source)?;
+    if (identical - 100.0).abs() > 0.01 {
+        return Err(format!(
+            "an image compared with itself scored {identical:.8}, expected 100"
+        ));
+    }
+    if compressed >= identical {
+        return Err(format!(
+            "a JPEG copy of an image scored {compressed:.8}, expected less than {identical:.8}"
+        ));
+    }
+    println!("Image files: ok ({identical:.8}, JPEG copy {compressed:.8})");
+
+    #[cfg(feature = "video")]
+    {
+        match float_clip_scores()? {
+            Some((integer, float)) if integer != float => {
+                return Err(format!(
+                    "a float clip scored {float:.8}, but its integer equivalent {integer:.8}"
+                ));
+            }
+            Some((_, float)) => println!("Float clip conversion: ok ({float:.8})"),
+            None => println!("Float clip conversion: skipped, VapourSynth can't run a script"),
+        }
+
+        render_graph(&[score, 100.0, score])?;
+        println!("Graph rendering: ok");
+    }
+    Ok(())
+}
+
+/// A smooth diagonal color gradient with a few hard edges, so blurring it is measurable
+fn gradient() -> Vec<[f32; 3]> {
+    (0..SIZE * SIZE)
+        .map(|i| {
+            let (x, y) = ((i % SIZE) as f32, (i / SIZE) as f32);
+            let edge = if (i % SIZE / 16 + i / SIZE / 16) % 2 == 0 {
+                0.2
+            } else {
+                0.0
+            };
+            let max = (SIZE - 1) as f32;
+            [
+                (x / max * 0.8 + edge).min(1.0),
+                (y / max * 0.8 + edge).min(1.0),
+                ((x + y) / (2.0 * max) * 0.8 + edge).min(1.0),
+            ]
+        })
+        .collect()
+}
+
+/// 3x3 box blur, clamping at the frame edges
+fn box_blur(pixels: &[[f32; 3]]) -> Vec<[f32; 3]> {
+    (0..SIZE * SIZE)
+        .map(|i| {
+            let (x, y) = (i % SIZE, i / SIZE);
+            let mut sum = [0.0; 3];
+            for ny in y.saturating_sub(1)..=(y + 1).min(SIZE - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(SIZE - 1) {
+                    for (total, value) in sum.iter_mut().zip(pixels[ny * SIZE + nx]) {
+                        *total += value;
+                    }
+                }
+            }
+            let count = ((x + 1).min(SIZE - 1) - x.saturating_sub(1) + 1)
+                * ((y + 1).min(SIZE - 1) - y.saturating_sub(1) + 1);
+            sum.map(|total| total / count as f32)
+        })
+        .collect()
+}
+
+/// Writes `pixels` to a PNG and a low quality JPEG, and scores the PNG against itself
+/// and against the JPEG the way the image command does
+fn image_file_scores(pixels: &[[f32; 3]]) -> Result<(f64, f64), String> {
+    use std::fs::File;
+
+    use image::codecs::jpeg::JpegEncoder;
+    use image::RgbImage;
+
+    let image = RgbImage::from_fn(SIZE as u32, SIZE as u32, |x, y| {
+        image::Rgb(pixels[y as usize * SIZE + x as usize].map(|c| (c * 255.0).round() as u8))
+    });
+    let dir = std::env::temp_dir();
+    let png = dir.join(format!("ssimulacra2_selftest_{}.png", std::process::id()));
+    let jpeg = dir.join(format!("ssimulacra2_selftest_{}.jpg", std::process::id()));
+    let write_png = || -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(File::create(&png)?, SIZE as u32, SIZE as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        // Tagged, so that only the JPEG warns about its color space being assumed
+        encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
+        encoder.write_header()?.write_image_data(&image)
+    };
+    let write_jpeg = || -> image::ImageResult<()> {
+        JpegEncoder::new_with_quality(File::create(&jpeg)?, 30).encode_image(&image)
+    };
+    let written = write_png()
+        .map_err(|e| e.to_string())
+        .and_then(|()| write_jpeg().map_err(|e| e.to_string()));
+    let scores = written
+        .map_err(|e| format!("failed to write an image: {e}"))
+        .map(|()| {
+            (
+                compare_images(&png, &png, None, &[], None).0,
+                compare_images(&png, &jpeg, None, &[], None).0,
+            )
+        });
+    let _ = std::fs::remove_file(&png);
+    let _ = std::fs::remove_file(&jpeg);
+    scores
+}
+
+fn score_frames(source: &[[f32; 3]], distorted: &[[f32; 3]]) -> Result<f64, String> {
+    let frame = |pixels: &[[f32; 3]]| {
+        Rgb::new(
+            pixels.to_vec(),
+            SIZE,
+            SIZE,
+            TransferCharacteristic::SRGB,
+            ColorPrimaries::BT709,
+        )
+        .map_err(|e| format!("failed to create a frame: {e}"))
+    };
+    compute_frame_ssimulacra2(frame(source)?, frame(distorted)?)
+        .map_err(|e| format!("failed to calculate ssimulacra2: {e}"))
+}
+
+/// Generates a 32-bit float YUV clip whose samples are all exact in binary, so that
+/// the integers they convert to don't depend on how VapourSynth rounds
+#[cfg(feature = "video")]
+const FLOAT_CLIP_SCRIPT: &str = r#"
+import vapoursynth as vs
+
+core = vs.core
+clip = core.std.BlankClip(format=vs.YUV444PS, width=128, height=128, length=1)
+clip = core.std.Expr(clip, ["X 0.0078125 *", "Y 0.00390625 * 0.25 -", "X Y - 0.00390625 *"])
+clip.set_output()
+"#;
+
+/// Decodes the clip of [`FLOAT_CLIP_SCRIPT`] with the float clip decoder, and scores the
+/// 16-bit frame its samples should convert to and the decoded frame against a slightly
+/// noisy copy. Both must score the same, which only holds if the decoder scales luma by
+/// 65535 and centers chroma. Returns `None` if VapourSynth can't run the script, e.g.
+/// when it isn't installed.
+#[cfg(feature = "video")]
+fn float_clip_scores() -> Result<Option<(f64, f64)>, String> {
+    use std::panic;
+
+    use av_metrics_decoders::{ChromaSampling, Decoder, Frame};
+    use ssimulacra2::{MatrixCoefficients, Yuv, YuvConfig};
+
+    use crate::float_clip::FloatClipDecoder;
+
+    let script =
+        std::env::temp_dir().join(format!("ssimulacra2_selftest_{}.vpy", std::process::id()));
+    std::fs::write(&script, FLOAT_CLIP_SCRIPT)
+        .map_err(|e| format!("failed to write a script: {e}"))?;
+    // The script is evaluated when the clip is opened. VapourSynth panics instead of
+    // failing if its scripting library is too old.
+    let decoder = panic::catch_unwind(|| FloatClipDecoder::open(&script));
+    let _ = std::fs::remove_file(&script);
+    let Ok(Ok(mut decoder)) = decoder else {
+        return Ok(None);
+    };
+    let bit_depth = decoder.get_bit_depth();
+    let decoded = decoder
+        .read_frame::<u16>()?
+        .ok_or("the float clip has no frames")?;
+
+    let max = ((1u32 << bit_depth) - 1) as f64;
+    let mid = (1u32 << (bit_depth - 1)) as f64;
+    let mut expected = [Vec::new(), Vec::new(), Vec::new()];
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let samples = [
+                x as f64 / 128.0 * max,
+                (y as f64 / 256.0 - 0.25) * max + mid,
+                (x as f64 - y as f64) / 256.0 * max + mid,
+            ];
+            for (plane, sample) in expected.iter_mut().zip(samples) {
+                plane.push(sample.round() as u16);
+            }
+        }
+    }
+    let noisy: Vec<Vec<u16>> = expected
+        .iter()
+        .map(|plane| {
+            plane
+                .iter()
+                .enumerate()
+                .map(|(i, &sample)| sample.saturating_add((i % 3) as u16 * 256))
+                .collect()
+        })
+        .collect();
+
+    let frame = |planes: &[Vec<u16>]| {
+        let mut frame: Frame<u16> = Frame::new_with_padding(SIZE, SIZE, ChromaSampling::Cs444, 0);
+        for (out, plane) in frame.planes.iter_mut().zip(planes) {
+            let bytes: Vec<u8> = plane
+                .iter()
+                .flat_map(|sample| sample.to_le_bytes())
+                .collect();
+            out.copy_from_raw_u8(&bytes, SIZE * 2, 2);
+        }
+        frame
+    };
+    let yuv = |frame: Frame<u16>| {
+        let config = YuvConfig {
+            bit_depth: bit_depth as u8,
+            subsampling_x: 0,
+            subsampling_y: 0,
+            full_range: true,
+            matrix_coefficients: MatrixCoefficients::BT709,
+            transfer_characteristics: TransferCharacteristic::BT1886,
+            color_primaries: ColorPrimaries::BT709,
+        };
+        Yuv::new(frame, config).map_err(|e| format!("failed to create a frame: {e}"))
+    };
+    let score = |source: Frame<u16>| {
+        compute_frame_ssimulacra2(&yuv(source)?, &yuv(frame(&noisy))?)
+            .map_err(|e| format!("failed to calculate ssimulacra2: {e}"))
+    };
+    Ok(Some((score(frame(&expected))?, score(decoded)?)))
+}
+
+/// Renders a tiny graph with the default backend, which panics when no usable font
+/// is installed, to a temporary file
+#[cfg(feature = "video")]
+fn render_graph(results: &[f64]) -> Result<(), String> {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use crate::graph::{GraphColor, GraphType};
+    use crate::video::write_graph;
+
+    let path =
+        std::env::temp_dir().join(format!("ssimulacra2_selftest_{}.png", std::process::id()));
+    let rendered = panic::catch_unwind(AssertUnwindSafe(|| {
+        write_graph(&path, results, (320, 240), GraphType::Line, GraphColor::Rgb)
+    }));
+    let _ = std::fs::remove_file(&path);
+    match rendered {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(format!("failed to write a graph: {e}")),
+        Err(_) => Err(
+            "rendering a graph panicked, most likely because no system font was found. \
+             Install a sans-serif font or use --graph-backend pure"
+                .to_string(),
+        ),
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use ssimulacra2::{
+    compute_frame_ssimulacra2, ColorPrimaries, Ssimulacra2Error, TransferCharacteristic,
+};
+use statrs::statistics::Data;
+
+use crate::error::Ssimu2Error;
+use crate::video::{PercentileMethod, Summary};
+use crate::{image_color_or_srgb, image_to_rgb};
+
+/// Substitutes `index` for the single `%d` or `%0Nd` placeholder in `pattern`,
+/// e.g. `frame_%04d.png` becomes `frame_0012.png`. A literal `%` is written as `%%`.
+pub fn expand_pattern(pattern: &str, index: usize) -> Result<String, String> {
+    let mut expanded = String::with_capacity(pattern.len() + 8);
+    let mut placeholders = 0;
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        if chars.next_if_eq(&'%').is_some() {
+            expanded.push('%');
+            continue;
+        }
+        let mut width = String::new();
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+            width.push(digit);
+        }
+        if chars.next() != Some('d') {
+            return Err(format!(
+                "invalid pattern `{pattern}`, only %d and %0Nd are supported"
+            ));
+        }
+        let width = width.parse::<usize>().unwrap_or(0);
+        expanded.push_str(&format!("{index:0width$}"));
+        placeholders += 1;
+    }
+    if placeholders != 1 {
+        return Err(format!(
+            "invalid pattern `{pattern}`, expected exactly one frame number placeholder"
+        ));
+    }
+    Ok(expanded)
+}
+
+/// Scores every pair of numbered images from `start`, either `frames` of them or until
+/// the first missing source image, and returns the statistics over all pairs.
+/// With `keep_going`, pairs that fail are logged and skipped instead of stopping the
+/// comparison, and returned along with the statistics over the others.
+pub fn compare_sequences(
+    source_pattern: &str,
+    distorted_pattern: &str,
+    start: usize,
+    frames: Option<usize>,
+    frame_threads: usize,
+    verbose: bool,
+    keep_going: bool,
+) -> Result<(Summary, Vec<(usize, Ssimu2Error)>), Ssimu2Error> {
+    let pattern_error = |pattern: &str, reason: String| Ssimu2Error::DecodeError {
+        input: pattern.to_string(),
+        reason,
+    };
+    let source_path =
+        |index| expand_pattern(source_pattern, index).map_err(|e| pattern_error(source_pattern, e));
+    let distorted_path = |index| {
+        expand_pattern(distorted_pattern, index).map_err(|e| pattern_error(distorted_pattern, e))
+    };
+
+    let end = match frames {
+        Some(frames) => start + frames,
+        None => {
+            let mut end = start;
+            while Path::new(&source_path(end)?).is_file() {
+                end += 1;
+            }
+            end
+        }
+    };
+    if end == start {
+        return Err(pattern_error(
+            source_pattern,
+            format!("no image found for frame {start}"),
+        ));
+    }
+
+    // Every image in a sequence is assumed to share the color space of the first one,
+    // which also keeps the warning for untagged images from repeating for every frame
+    let source_color = image_color_or_srgb(Path::new(&source_path(start)?));
+    let distorted_color = image_color_or_srgb(Path::new(&distorted_path(start)?));
+
+    let next = AtomicUsize::new(start);
+    let results = Mutex::new(BTreeMap::new());
+    let failures = Mutex::new(BTreeMap::new());
+    let first_error = Mutex::new(None);
+    thread::scope(|scope| {
+        for _ in 0..frame_threads {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= end || first_error.lock().unwrap().is_some() {
+                    break;
+                }
+                let score = source_path(index).and_then(|source| {
+                    score_pair(
+                        &source,
+                        &distorted_path(index)?,
+                        source_color,
+                        distorted_color,
+                    )
+                });
+                match score {
+                    Ok(score) => {
+                        results.lock().unwrap().insert(index, score);
+                    }
+                    Err(e) if keep_going => {
+                        eprintln!("Error: {e}");
+                        failures.lock().unwrap().insert(index, e);
+                    }
+                    Err(e) => {
+                        first_error.lock().unwrap().get_or_insert(e);
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    if let Some(e) = first_error.into_inner().unwrap() {
+        return Err(e);
+    }
+
+    let results = results.into_inner().unwrap();
+    let failures = failures.into_inner().unwrap();
+    if verbose {
+        for index in start..end {
+            match results.get(&index) {
+                Some(score) => println!("Frame {index}: {score:.8}"),
+                None => println!("Frame {index}: FAILED"),
+            }
+        }
+    }
+    if results.is_empty() {
+        return Err(Ssimu2Error::SequenceFailures {
+            failed: failures.len(),
+            total: failures.len(),
+        });
+    }
+    let summary = Summary::new(
+        &mut Data::new(results.into_values().collect()),
+        PercentileMethod::default(),
+    );
+    Ok((summary, failures.into_iter().collect()))
+}
+
+fn score_pair(
+    source: &str,
+    distorted: &str,
+    source_color: (TransferCharacteristic, ColorPrimaries),
+    distorted_color: (TransferCharacteristic, ColorPrimaries),
+) -> Result<f64, Ssimu2Error> {
+    let load = |path: &str, color| {
+        let decode_error = |reason: String| Ssimu2Error::DecodeError {
+            input: path.to_string(),
+            reason,
+        };
+        let image = image::open(path).map_err(|e| decode_error(e.to_string()))?;
+        image_to_rgb(&image, color).map_err(|e| decode_error(e.to_string()))
+    };
+    let source_data = load(source, source_color)?;
+    let distorted_data = load(distorted, distorted_color)?;
+    let (src_size, dst_size) = (
+        (source_data.width(), source_data.height()),
+        (distorted_data.width(), distorted_data.height()),
+    );
+    compute_frame_ssimulacra2(source_data, distorted_data).map_err(|e| match e {
+        Ssimulacra2Error::NonMatchingImageDimensions => Ssimu2Error::ResolutionMismatch {
+            src: src_size,
+            dst: dst_size,
+        },
+        e => Ssimu2Error::DecodeError {
+            input: distorted.to_string(),
+            reason: e.to_string(),
+        },
+    })
+}
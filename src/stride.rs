@@ -0,0 +1,18 @@
+use ssimulacra2::LinearRgb;
+
+/// Keeps every `stride`th pixel of each row, for `--pixel-stride`. Each row starts one
+/// pixel further along than the one above, so the kept pixels form a diagonal pattern
+/// that samples every column. The result is `stride` times narrower, which makes scoring
+/// about that much faster, but the metric's blurs and downscales then see a distorted
+/// picture, so the score is only a rough estimate.
+pub fn stride_pixels(rgb: &LinearRgb, stride: usize) -> LinearRgb {
+    let (width, height) = (rgb.width(), rgb.height());
+    let data = rgb.data();
+    let strided_width = (width / stride).max(1);
+    let mut out = Vec::with_capacity(strided_width * height);
+    for y in 0..height {
+        let row = &data[y * width..(y + 1) * width];
+        out.extend((0..strided_width).map(|x| row[(x * stride + y % stride).min(width - 1)]));
+    }
+    LinearRgb::new(out, strided_width, height).expect("Data matches the frame dimensions")
+}
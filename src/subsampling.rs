@@ -0,0 +1,146 @@
+use av_metrics_decoders::{ChromaSampling, Frame, Pixel};
+
+/// Chroma subsampling to score an input in instead of the one it is decoded in, for
+/// `--src-subsampling`/`--dst-subsampling`
+#[derive(Clone, Copy, Debug)]
+pub struct SubsamplingOverride {
+    pub subsampling: ChromaSampling,
+    pub bit_depth: usize,
+}
+
+impl SubsamplingOverride {
+    /// Chroma layout of the resampled frames. Gray frames keep full resolution chroma
+    /// planes, as the color conversion reads chroma for every pixel.
+    pub const fn layout(self) -> ChromaSampling {
+        match self.subsampling {
+            ChromaSampling::Cs400 => ChromaSampling::Cs444,
+            subsampling => subsampling,
+        }
+    }
+
+    /// Resamples the chroma planes of `frame` to the overridden subsampling. Each chroma
+    /// sample becomes the average of the decoded samples covering the same luma area, so
+    /// decimating averages neighbours and expanding repeats them. Gray frames get neutral
+    /// chroma, and 4:0:0 replaces the chroma with neutral chroma at full resolution.
+    pub fn apply<T: Pixel>(self, frame: Frame<T>) -> Frame<T> {
+        let Frame {
+            planes: [luma, cb, cr],
+        } = frame;
+        let (width, height) = (luma.cfg.width, luma.cfg.height);
+        let gray = self.subsampling == ChromaSampling::Cs400 || cb.cfg.width == 0;
+        let layout = self.layout();
+        let (to_x, to_y) = layout.get_decimation().expect("Layout has chroma planes");
+        if !gray && (cb.cfg.xdec, cb.cfg.ydec) == (to_x, to_y) {
+            return Frame {
+                planes: [luma, cb, cr],
+            };
+        }
+
+        let mut output: Frame<T> = Frame::new_with_padding(width, height, layout, 0);
+        let neutral = 1u32 << (self.bit_depth - 1);
+        for (out, decoded) in output.planes[1..].iter_mut().zip([cb, cr]) {
+            let (from_x, from_y) = (decoded.cfg.xdec, decoded.cfg.ydec);
+            let out_width = out.cfg.width;
+            for (y, row) in out.rows_iter_mut().enumerate() {
+                for (x, sample) in row[..out_width].iter_mut().enumerate() {
+                    if gray {
+                        *sample = T::cast_from(neutral as u16);
+                        continue;
+                    }
+                    // Luma area the output sample covers, clipped to the frame
+                    let xs = x << to_x..((x + 1) << to_x).min(width);
+                    let ys = y << to_y..((y + 1) << to_y).min(height);
+                    let (mut sum, mut count) = (0u32, 0u32);
+                    for luma_y in ys {
+                        for luma_x in xs.clone() {
+                            let value: u32 = decoded
+                                .p(
+                                    (luma_x >> from_x).min(decoded.cfg.width - 1),
+                                    (luma_y >> from_y).min(decoded.cfg.height - 1),
+                                )
+                                .into();
+                            sum += value;
+                            count += 1;
+                        }
+                    }
+                    *sample = T::cast_from(((sum + count / 2) / count) as u16);
+                }
+            }
+        }
+        output.planes[0] = luma;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 8x8 8-bit frame in `subsampling`, with every chroma sample set by `chroma(x, y)`
+    fn frame(subsampling: ChromaSampling, chroma: impl Fn(usize, usize) -> u8) -> Frame<u8> {
+        let mut frame = Frame::new_with_padding(8, 8, subsampling, 0);
+        for plane in &mut frame.planes[1..] {
+            for (y, row) in plane.rows_iter_mut().enumerate() {
+                for (x, sample) in row.iter_mut().enumerate() {
+                    *sample = chroma(x, y);
+                }
+            }
+        }
+        frame
+    }
+
+    fn resample(frame: Frame<u8>, subsampling: ChromaSampling) -> Frame<u8> {
+        SubsamplingOverride {
+            subsampling,
+            bit_depth: 8,
+        }
+        .apply(frame)
+    }
+
+    #[test]
+    fn decimating_averages_the_covered_samples() {
+        let output = resample(
+            frame(ChromaSampling::Cs444, |x, y| (x + 2 * y) as u8),
+            ChromaSampling::Cs420,
+        );
+        let cb = &output.planes[1];
+        assert_eq!((cb.cfg.xdec, cb.cfg.ydec), (1, 1));
+        assert_eq!((cb.cfg.width, cb.cfg.height), (4, 4));
+        // The top left sample covers 0, 1, 2 and 3
+        assert_eq!(cb.p(0, 0), 2);
+        assert_eq!(cb.p(1, 0), 4);
+    }
+
+    #[test]
+    fn expanding_repeats_samples() {
+        let output = resample(
+            frame(ChromaSampling::Cs420, |x, y| (10 * x + y) as u8),
+            ChromaSampling::Cs444,
+        );
+        let cr = &output.planes[2];
+        assert_eq!((cr.cfg.xdec, cr.cfg.ydec), (0, 0));
+        assert_eq!(cr.p(2, 3), 11);
+        assert_eq!(cr.p(3, 2), 11);
+    }
+
+    #[test]
+    fn gray_has_neutral_chroma() {
+        let output = resample(
+            frame(ChromaSampling::Cs420, |_, _| 0),
+            ChromaSampling::Cs400,
+        );
+        assert_eq!(output.planes[1].cfg.xdec, 0);
+        assert!(output.planes[1..]
+            .iter()
+            .all(|plane| plane.iter().all(|sample| sample == 128)));
+    }
+
+    #[test]
+    fn matching_layout_is_unchanged() {
+        let output = resample(
+            frame(ChromaSampling::Cs422, |x, _| x as u8),
+            ChromaSampling::Cs422,
+        );
+        assert_eq!(output.planes[1].p(3, 0), 3);
+    }
+}
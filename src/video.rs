@@ -1,22 +1,56 @@
-use std::collections::BTreeMap;
-use std::io::stderr;
-use std::sync::{mpsc, Arc, Mutex};
-use std::time::Duration;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::io::{self, stderr, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, Once};
+use std::time::{Duration, Instant};
 use std::{
     path::{Path, PathBuf},
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use av_metrics_decoders::{y4m::new_decoder_from_stdin, Decoder, VapoursynthDecoder};
+use anyhow::{anyhow, bail};
+use av_metrics_decoders::{ChromaSampling, Decoder, Frame, VideoDetails};
+use clap::ValueEnum;
+use core_affinity::CoreId;
 use crossterm::tty::IsTty;
-use image::ColorType;
+use image::imageops::{self, FilterType};
+use image::{ColorType, ImageBuffer};
 use indicatif::{HumanDuration, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
 use num_traits::FromPrimitive;
+use rand::{rngs::StdRng, seq::index, SeedableRng};
+use serde_json::json;
 use ssimulacra2::{
-    compute_frame_ssimulacra2, ColorPrimaries, MatrixCoefficients, Pixel, TransferCharacteristic,
-    Yuv, YuvConfig,
+    compute_frame_ssimulacra2, ColorPrimaries, LinearRgb, MatrixCoefficients, Pixel, Rgb,
+    Ssimulacra2Error, TransferCharacteristic, Yuv, YuvConfig,
 };
-use statrs::statistics::{Data, Distribution, Median, OrderStatistics};
+use statrs::distribution::{ContinuousCDF, StudentsT};
+use statrs::statistics::{Data, Distribution, Max, Min, OrderStatistics};
+use vapoursynth::prelude::*;
+
+use crate::affinity::{pin_worker, worker_cores};
+use crate::command::resolved_command;
+use crate::decode::{
+    count_y4m_frames, detect_color, detect_sar, first_keyframe, intra_frames, is_piped,
+    vapoursynth_environment, DeclaredColor, VideoInput,
+};
+use crate::diagnostics::{note, report, warning};
+use crate::error::Ssimu2Error;
+use crate::graph::{
+    save_graph, score_range, write_pure_graph, GraphBackend, GraphColor, GraphType,
+};
+use crate::hdr::{highlight_weight, is_hdr, weighted_mean, HighlightWeights};
+use crate::heatmap::write_heatmap;
+use crate::ictcp::{ictcp_to_linear_rgb, is_ictcp};
+use crate::mask::Mask;
+use crate::output::{
+    value_key, write_csv, write_json, Emit, FrameOutput, Manifest, StagedOutputs, SCHEMA_VERSION,
+};
+use crate::overlay::OverlayWriter;
+use crate::pattern::{parse_pattern, PatternDecoder};
+use crate::raw::PipedFormat;
+use crate::stride::stride_pixels;
+use crate::subsampling::SubsamplingOverride;
 
 const PROGRESS_CHARS: &str = "█▉▊▋▌▍▎▏  ";
 const INDICATIF_PROGRESS_TEMPLATE: &str = if cfg!(windows) {
@@ -60,8 +94,12 @@ fn pretty_progress_style() -> ProgressStyle {
                 if state.pos() == 0 || state.elapsed().as_secs_f32() < f32::EPSILON {
                     write!(w, "unknown").unwrap();
                 } else {
+                    // The length is the number of sampled frames, and the time per scored
+                    // frame includes decoding the frames skipped by the increment
                     let spf = state.elapsed().as_secs_f32() / state.pos() as f32;
-                    let remaining = state.len().unwrap_or(0) - state.pos();
+                    // The position can pass an estimated length that turned out too low,
+                    // which must not wrap around into an ETA of centuries
+                    let remaining = state.len().unwrap_or(0).saturating_sub(state.pos());
                     write!(
                         w,
                         "{:#}",
@@ -114,225 +152,1960 @@ fn pretty_spinner_style() -> ProgressStyle {
         .progress_chars(PROGRESS_CHARS)
 }
 
-type VideoCompareMutex<E, F> = Arc<Mutex<VideoCompare<E, F>>>;
+/// Sorted indices of the frames that should be scored
+type FrameIter = Box<dyn Iterator<Item = usize> + Send>;
+
+/// A decoded source and distorted frame pair, tagged with its frame index
+type FramePair<S, D> = (usize, Frame<S>, Frame<D>);
+
+struct VideoCompare {
+    /// Index of the frame the decoders will return on their next read
+    decoder_pos: usize,
+    frames: FrameIter,
+    source: VideoInput,
+    distorted: VideoInput,
+    /// Names of the inputs, for decoding errors
+    source_name: String,
+    distorted_name: String,
+    /// Subsampling the frames of each input are resampled to before scoring
+    source_subsampling: Option<SubsamplingOverride>,
+    distorted_subsampling: Option<SubsamplingOverride>,
+}
+
+impl VideoCompare {
+    /// Decodes the next selected pair of frames, discarding any unselected frames
+    /// in between. Returns `None` once either input runs out of frames.
+    fn read_next<S: Pixel, D: Pixel>(
+        &mut self,
+        verbose: bool,
+    ) -> Result<Option<FramePair<S, D>>, Ssimu2Error> {
+        let Some(frame_idx) = self.frames.next() else {
+            return Ok(None);
+        };
+
+        while self.decoder_pos < frame_idx {
+            if self.read_pair::<S, D>()?.is_none() {
+                return Ok(None);
+            }
+            if verbose {
+                report!("Frame {}: skip", self.decoder_pos);
+            }
+            self.decoder_pos += 1;
+        }
+
+        let frame_pair = self.read_pair()?;
+        self.decoder_pos += 1;
+        Ok(frame_pair)
+    }
 
-struct VideoCompare<E: Decoder, F: Decoder> {
-    current_frame: usize,
-    next_frame: usize,
-    source: E,
-    distorted: F,
+    /// Reads the next frame of both inputs, tagged with the current decoder position,
+    /// or `None` if either ran out
+    fn read_pair<S: Pixel, D: Pixel>(&mut self) -> Result<Option<FramePair<S, D>>, Ssimu2Error> {
+        let src_frame = self
+            .source
+            .read_frame()
+            .map_err(|reason| Ssimu2Error::DecodeError {
+                input: self.source_name.clone(),
+                reason,
+            })?;
+        let dst_frame = self
+            .distorted
+            .read_frame()
+            .map_err(|reason| Ssimu2Error::DecodeError {
+                input: self.distorted_name.clone(),
+                reason,
+            })?;
+        let src_frame = src_frame.map(|frame| match self.source_subsampling {
+            Some(layout) => layout.apply(frame),
+            None => frame,
+        });
+        let dst_frame = dst_frame.map(|frame| match self.distorted_subsampling {
+            Some(layout) => layout.apply(frame),
+            None => frame,
+        });
+        let frame_idx = self.decoder_pos;
+        Ok(src_frame
+            .zip(dst_frame)
+            .map(|(src_frame, dst_frame)| (frame_idx, src_frame, dst_frame)))
+    }
 }
 
-fn calc_score<S: Pixel, D: Pixel, E: Decoder, F: Decoder>(
-    mtx: &VideoCompareMutex<E, F>,
-    src_yuvcfg: &YuvConfig,
-    dst_yuvcfg: &YuvConfig,
-    inc: usize,
-    end_frame: Option<usize>,
+/// How the decoding and scoring threads run, and what they measure on the side
+struct Workers {
+    frame_threads: usize,
+    /// Decoded frame pairs queued ahead of the workers
+    buffer_frames: usize,
     verbose: bool,
-) -> Option<(usize, f64)> {
-    let (frame_idx, (src_frame, dst_frame)) = {
-        let mut guard = mtx.lock().unwrap();
+    /// Set to stop decoding new frames
+    stop: Arc<AtomicBool>,
+    timings: Option<Arc<Timings>>,
+    thread_stats: Option<Arc<Vec<ThreadStats>>>,
+    worker_cores: Option<Arc<Vec<CoreId>>>,
+}
+
+/// Decodes frames on a single coordinator thread and scores them on `frame_threads`
+/// worker threads, so decoding never blocks on a lock shared with the scoring.
+fn spawn_workers<S: Pixel, D: Pixel>(
+    mut video_compare: VideoCompare,
+    workers: &Workers,
+    scorer: &Arc<FrameScorer>,
+    overlay: Option<OverlayWriter>,
+    result_tx: &mpsc::Sender<Result<(usize, f64), Ssimu2Error>>,
+) {
+    let Workers {
+        frame_threads,
+        buffer_frames,
+        verbose,
+        ref stop,
+        ref timings,
+        ref thread_stats,
+        ref worker_cores,
+    } = *workers;
 
-        let distance_to_next = guard.next_frame - guard.current_frame;
+    // Bounding the queue keeps fast decoders from buffering ahead without limit.
+    // Every pair is tagged with the position it was decoded in, for the overlay output.
+    let (frame_tx, frame_rx) =
+        crossbeam_channel::bounded::<(usize, FramePair<S, D>)>(buffer_frames);
 
-        if let Some(end_frame) = end_frame {
-            if guard.next_frame >= end_frame {
-                return None;
+    // Scored frames arrive out of order, so the writer reorders them by decoding position
+    let overlay_tx = overlay.map(|mut overlay| {
+        let (overlay_tx, overlay_rx) = mpsc::channel::<(usize, f64, Frame<D>)>();
+        let result_tx = result_tx.clone();
+        std::thread::spawn(move || {
+            for (position, score, frame) in overlay_rx {
+                if let Err(e) = overlay.push(position, score, frame) {
+                    let _ = result_tx.send(Err(e));
+                    break;
+                }
             }
-        }
+        });
+        overlay_tx
+    });
 
-        for ii in 1..distance_to_next {
-            let _src_frame = guard.source.read_video_frame::<S>();
-            let _dst_frame = guard.distorted.read_video_frame::<D>();
-            if _src_frame.is_none() || _dst_frame.is_none() {
+    let decode_timings = timings.clone();
+    let decode_result_tx = result_tx.clone();
+    let stop = Arc::clone(stop);
+    std::thread::spawn(move || {
+        for position in 0.. {
+            if stop.load(Ordering::Relaxed) {
                 break;
             }
-            if verbose {
-                println!("Frame {}: skip", guard.current_frame + ii);
+            let start = Instant::now();
+            let frame_pair = video_compare.read_next::<S, D>(verbose);
+            if let Some(timings) = &decode_timings {
+                Timings::add(&timings.decode_nanos, start.elapsed());
+            }
+            let frame_pair = match frame_pair {
+                Ok(Some(frame_pair)) => frame_pair,
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = decode_result_tx.send(Err(e));
+                    break;
+                }
+            };
+            if frame_tx.send((position, frame_pair)).is_err() {
+                break;
             }
         }
+    });
 
-        let src_frame = guard.source.read_video_frame::<S>();
-        let dst_frame = guard.distorted.read_video_frame::<D>();
+    for thread in 0..frame_threads {
+        let frame_rx = frame_rx.clone();
+        let result_tx = result_tx.clone();
+        let scorer = Arc::clone(scorer);
+        let timings = timings.clone();
+        let thread_stats = thread_stats.clone();
+        let worker_cores = worker_cores.clone();
+        let overlay_tx = overlay_tx.clone();
 
-        let curr_frame = guard.next_frame;
-        guard.current_frame = guard.next_frame;
-        guard.next_frame += inc;
+        std::thread::spawn(move || {
+            if let Some(cores) = &worker_cores {
+                pin_worker(cores, thread);
+            }
+            let stats = thread_stats.as_ref().map(|stats| &stats[thread]);
+            let mut wait_start = Instant::now();
+            for (position, (frame_idx, src_frame, dst_frame)) in frame_rx {
+                let start = Instant::now();
+                if let Some(stats) = stats {
+                    Timings::add(&stats.wait_nanos, start - wait_start);
+                }
+                let overlay_frame = overlay_tx.as_ref().map(|_| dst_frame.clone());
+                let score = calc_score(frame_idx, src_frame, dst_frame, &scorer);
+                if let Some(timings) = &timings {
+                    Timings::add(&timings.compute_nanos, start.elapsed());
+                }
+                if let (Some(overlay_tx), Some(frame), Ok(score)) =
+                    (&overlay_tx, overlay_frame, &score)
+                {
+                    let _ = overlay_tx.send((position, *score, frame));
+                }
+                if result_tx
+                    .send(score.map(|score| (frame_idx, score)))
+                    .is_err()
+                {
+                    break;
+                }
+                wait_start = Instant::now();
+                if let Some(stats) = stats {
+                    stats.frames.fetch_add(1, Ordering::Relaxed);
+                    Timings::add(&stats.active_nanos, wait_start - start);
+                }
+            }
+        });
+    }
+}
+
+/// Target resolution for `--downscale`
+#[derive(Clone, Copy, Debug)]
+pub enum Downscale {
+    /// Divide both dimensions by this factor
+    Factor(f64),
+    /// Scale to exactly this width and height
+    Size(u32, u32),
+}
 
-        if let (Some(sf), Some(df)) = (src_frame, dst_frame) {
-            (curr_frame, (sf, df))
+impl Downscale {
+    fn target_size(self, width: usize, height: usize) -> (u32, u32) {
+        match self {
+            Downscale::Factor(factor) => (
+                (width as f64 / factor).round().max(1.0) as u32,
+                (height as f64 / factor).round().max(1.0) as u32,
+            ),
+            Downscale::Size(width, height) => (width, height),
+        }
+    }
+
+    /// Resizes a frame in linear light, so source and distorted are filtered identically
+    fn apply(self, rgb: LinearRgb) -> LinearRgb {
+        let (width, height) = self.target_size(rgb.width(), rgb.height());
+        let buffer = ImageBuffer::<image::Rgb<f32>, _>::from_raw(
+            rgb.width() as u32,
+            rgb.height() as u32,
+            rgb.into_data().into_iter().flatten().collect::<Vec<_>>(),
+        )
+        .expect("Frame data matches the frame dimensions");
+        let resized = imageops::resize(&buffer, width, height, FilterType::Triangle);
+        LinearRgb::new(
+            resized
+                .into_raw()
+                .chunks_exact(3)
+                .map(|chunk| [chunk[0], chunk[1], chunk[2]])
+                .collect(),
+            width as usize,
+            height as usize,
+        )
+        .expect("Resized data matches the target dimensions")
+    }
+}
+
+/// Luma range for `--luma-window`, as fractions of the signal range from black (0)
+/// to white (1)
+#[derive(Clone, Copy, Debug)]
+pub struct LumaWindow {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl LumaWindow {
+    /// Mask of the pixels whose source luma is inside the window. The luma plane is
+    /// never subsampled, so the mask has the resolution of the frame.
+    fn mask<T: Pixel>(self, frame: &Frame<T>, config: &YuvConfig) -> Mask {
+        let plane = &frame.planes[0];
+        let (width, height) = (plane.cfg.width, plane.cfg.height);
+        let shift = config.bit_depth.saturating_sub(8);
+        let (black, range) = if config.full_range {
+            (0.0, f64::from((1u32 << config.bit_depth) - 1))
         } else {
-            return None;
+            (f64::from(16u32 << shift), f64::from(219u32 << shift))
+        };
+
+        let mut weights = Vec::with_capacity(width * height);
+        for row in plane.rows_iter().take(height) {
+            for &sample in &row[..width] {
+                let sample: u32 = sample.into();
+                let luma = (f64::from(sample) - black) / range;
+                weights.push(if (self.min..=self.max).contains(&luma) {
+                    1.0
+                } else {
+                    0.0
+                });
+            }
+        }
+        Mask::from_weights(weights, width, height)
+    }
+}
+
+/// Light in which frames are compared, for `--difference-space`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum DifferenceSpace {
+    /// Linear light, which SSIMULACRA2 converts to XYB itself. This is the standard
+    /// metric.
+    #[default]
+    Linear,
+    /// Gamma encoded with a 1/2.2 power before scoring, which SSIMULACRA2 then takes
+    /// for linear light. Differences in dark areas weigh more, and in bright areas less.
+    Gamma,
+}
+
+impl DifferenceSpace {
+    /// Re-encodes a frame in linear light for scoring in this space
+    fn apply(self, rgb: LinearRgb) -> LinearRgb {
+        match self {
+            DifferenceSpace::Linear => rgb,
+            DifferenceSpace::Gamma => {
+                let (width, height) = (rgb.width(), rgb.height());
+                let data = rgb
+                    .into_data()
+                    .into_iter()
+                    .map(|pixel| pixel.map(|value| value.max(0.0).powf(1.0 / 2.2)))
+                    .collect();
+                LinearRgb::new(data, width, height).expect("Data matches the frame dimensions")
+            }
+        }
+    }
+}
+
+/// Range of the sample values of an input, for `--src-range` and `--dst-range`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorRange {
+    /// Use the range declared by the input, or limited if it declares none
+    #[default]
+    Auto,
+    Limited,
+    Full,
+}
+
+impl ColorRange {
+    /// Returns whether `input` is full range, given the range it declares, and where that
+    /// decision came from. An explicit range wins over the declared one, which wins over
+    /// assuming limited range.
+    fn resolve(self, input: &str, declared: Option<bool>) -> (bool, RangeOrigin) {
+        let explicit = match self {
+            ColorRange::Auto => {
+                return match declared {
+                    Some(full_range) => (full_range, RangeOrigin::Declared),
+                    None => {
+                        warning!("{input} does not declare a color range, assuming limited");
+                        (false, RangeOrigin::Assumed)
+                    }
+                };
+            }
+            ColorRange::Limited => false,
+            ColorRange::Full => true,
+        };
+        if declared.is_some_and(|full_range| full_range != explicit) {
+            note!(
+                "{input} declares {} range, using {} range from the command line instead",
+                range_name(!explicit),
+                range_name(explicit)
+            );
         }
+        (explicit, RangeOrigin::Explicit)
+    }
+}
+
+fn range_name(full_range: bool) -> &'static str {
+    if full_range {
+        "full"
+    } else {
+        "limited"
+    }
+}
+
+/// Where the range of an input came from, in order of precedence
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeOrigin {
+    /// `--src-range` or `--dst-range`
+    Explicit,
+    /// The frame properties or y4m header of the input
+    Declared,
+    /// Nothing declared a range, so limited was assumed
+    Assumed,
+}
+
+impl RangeOrigin {
+    fn as_str(self) -> &'static str {
+        match self {
+            RangeOrigin::Explicit => "command line",
+            RangeOrigin::Declared => "input",
+            RangeOrigin::Assumed => "assumed",
+        }
+    }
+}
+
+/// Time spent decoding and scoring frames, collected for `--profile`
+#[derive(Default)]
+struct Timings {
+    decode_nanos: AtomicU64,
+    compute_nanos: AtomicU64,
+}
+
+impl Timings {
+    fn add(counter: &AtomicU64, elapsed: Duration) {
+        counter.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn reset(&self) {
+        self.decode_nanos.store(0, Ordering::Relaxed);
+        self.compute_nanos.store(0, Ordering::Relaxed);
+    }
+
+    /// Prints how the total time splits between decoding and scoring. Scoring time is
+    /// summed across all worker threads, so it can exceed the wall clock time.
+    fn print(&self, frame_threads: usize) {
+        let decode = Duration::from_nanos(self.decode_nanos.load(Ordering::Relaxed));
+        let compute = Duration::from_nanos(self.compute_nanos.load(Ordering::Relaxed));
+        let total = (decode + compute).as_secs_f64().max(f64::EPSILON);
+        report!(
+            "decode: {:.0}% ({:.2}s), compute: {:.0}% ({:.2}s across {} thread(s))",
+            decode.as_secs_f64() / total * 100.0,
+            decode.as_secs_f64(),
+            compute.as_secs_f64() / total * 100.0,
+            compute.as_secs_f64(),
+            frame_threads
+        );
+    }
+}
+
+/// Work done by a single worker thread, collected for `--thread-stats`
+#[derive(Default)]
+struct ThreadStats {
+    frames: AtomicUsize,
+    active_nanos: AtomicU64,
+    /// Time spent waiting for the decoder thread to queue the next frame pair
+    wait_nanos: AtomicU64,
+}
+
+impl ThreadStats {
+    fn reset(&self) {
+        self.frames.store(0, Ordering::Relaxed);
+        self.active_nanos.store(0, Ordering::Relaxed);
+        self.wait_nanos.store(0, Ordering::Relaxed);
+    }
+
+    /// Prints one line per worker thread. Threads that spend much of their time waiting
+    /// are starved by decoding, so more `--frame-threads` won't make the run faster.
+    fn print(stats: &[ThreadStats]) {
+        for (thread, stats) in stats.iter().enumerate() {
+            let active = Duration::from_nanos(stats.active_nanos.load(Ordering::Relaxed));
+            let wait = Duration::from_nanos(stats.wait_nanos.load(Ordering::Relaxed));
+            let total = (active + wait).as_secs_f64().max(f64::EPSILON);
+            report!(
+                "thread {thread}: {} frame(s), active {:.2}s, waiting {:.2}s ({:.0}%)",
+                stats.frames.load(Ordering::Relaxed),
+                active.as_secs_f64(),
+                wait.as_secs_f64(),
+                wait.as_secs_f64() / total * 100.0
+            );
+        }
+    }
+}
+
+/// How the percentiles of the summary are computed. The names match the methods of
+/// numpy's `percentile`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PercentileMethod {
+    /// Interpolates between the closest ranks following Hyndman and Fan's definition 8,
+    /// which is what statrs implements
+    #[default]
+    MedianUnbiased,
+    /// Interpolates linearly between the closest ranks, the default of numpy
+    Linear,
+    /// The score at the closest rank, with ties going to the even rank
+    Nearest,
+    /// The score at the closest rank below
+    Lower,
+    /// The score at the closest rank above
+    Higher,
+}
+
+impl PercentileMethod {
+    fn percentile(self, data: &mut Data<Vec<f64>>, p: usize) -> f64 {
+        if self == PercentileMethod::MedianUnbiased {
+            return data.percentile(p);
+        }
+        let mut sorted: Vec<f64> = data.iter().copied().collect();
+        if sorted.is_empty() {
+            return f64::NAN;
+        }
+        sorted.sort_by(f64::total_cmp);
+        let rank = p as f64 / 100.0 * (sorted.len() - 1) as f64;
+        let (below, fraction) = (rank.floor() as usize, rank.fract());
+        let (lower, upper) = (sorted[below], sorted[rank.ceil() as usize]);
+        match self {
+            PercentileMethod::Linear => lower + fraction * (upper - lower),
+            PercentileMethod::Nearest if fraction < 0.5 => lower,
+            PercentileMethod::Nearest if fraction == 0.5 && below % 2 == 0 => lower,
+            PercentileMethod::Lower => lower,
+            PercentileMethod::Nearest
+            | PercentileMethod::Higher
+            | PercentileMethod::MedianUnbiased => upper,
+        }
+    }
+}
+
+/// Summary statistics over the scores of every compared frame
+pub struct Summary {
+    pub frames: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub std_dev: f64,
+    /// Standard error of the mean
+    pub std_err: f64,
+    /// 95% confidence interval of the mean, from Student's t distribution
+    pub ci95: (f64, f64),
+    pub p5: f64,
+    pub p95: f64,
+    pub min: f64,
+}
+
+impl Summary {
+    pub fn new(data: &mut Data<Vec<f64>>, percentile_method: PercentileMethod) -> Self {
+        let mean = data.mean().unwrap();
+        let std_dev = data.std_dev().unwrap();
+        let std_err = std_dev / (data.len() as f64).sqrt();
+        // A single frame has no spread to estimate the interval from
+        let t = StudentsT::new(0.0, 1.0, data.len() as f64 - 1.0)
+            .map_or(f64::NAN, |t| t.inverse_cdf(0.975));
+        Self {
+            frames: data.len(),
+            mean,
+            median: data.median(),
+            std_dev,
+            std_err,
+            ci95: (mean - t * std_err, mean + t * std_err),
+            p5: percentile_method.percentile(data, 5),
+            p95: percentile_method.percentile(data, 95),
+            min: data.min(),
+        }
+    }
+
+    /// Prints the summary block, headed by what was scored and `label`, e.g. "Video"
+    /// and "Score"
+    pub fn print(&self, input: &str, label: &str) {
+        report!("{input} {label} for {} frames", self.frames);
+        report!("Mean: {:.8}", self.mean);
+        report!("Median: {:.8}", self.median);
+        report!("Std Dev: {:.8}", self.std_dev);
+        report!("Std Error: {:.8}", self.std_err);
+        report!("95% CI: {:.8} - {:.8}", self.ci95.0, self.ci95.1);
+        report!("5th Percentile: {:.8}", self.p5);
+        report!("95th Percentile: {:.8}", self.p95);
+    }
+
+    /// Prints the main statistics as `key=value` pairs on a single line
+    pub fn print_oneline(&self) {
+        report!(
+            "mean={:.8} median={:.8} p5={:.8} p95={:.8} n={}",
+            self.mean,
+            self.median,
+            self.p5,
+            self.p95,
+            self.frames
+        );
+    }
+}
+
+/// Prints one aligned row of summary statistics per distorted input
+pub fn print_table(rows: &[(&str, Option<Summary>)]) {
+    let input_width = rows
+        .iter()
+        .map(|(input, _)| input.chars().count())
+        .chain(std::iter::once("input".len()))
+        .max()
+        .unwrap_or_default();
+
+    report!(
+        "{:<input_width$} | {:>12} | {:>12} | {:>12} | {:>12} | {:>12}",
+        "input",
+        "mean",
+        "median",
+        "p5",
+        "p95",
+        "min"
+    );
+    report!(
+        "{}-|-{}",
+        "-".repeat(input_width),
+        vec!["-".repeat(12); 5].join("-|-")
+    );
+    for (input, summary) in rows {
+        let Some(summary) = summary else {
+            report!("{input:<input_width$} | {:>12}", "FAILED");
+            continue;
+        };
+        report!(
+            "{:<input_width$} | {:>12.8} | {:>12.8} | {:>12.8} | {:>12.8} | {:>12.8}",
+            input,
+            summary.mean,
+            summary.median,
+            summary.p5,
+            summary.p95,
+            summary.min
+        );
+    }
+}
+
+/// Prints the mean and minimum score of every scene for `--scenes`, where each cut is the
+/// first frame of a new scene. Scenes without any scored frame are left out.
+fn print_scene_summary(frame_scores: &[(usize, f64)], cuts: &BTreeSet<usize>) {
+    report!(
+        "{:>8} | {:>8} | {:>8} | {:>12} | {:>12}",
+        "start",
+        "end",
+        "frames",
+        "mean",
+        "min"
+    );
+    report!(
+        "{}-|-{}",
+        vec!["-".repeat(8); 3].join("-|-"),
+        vec!["-".repeat(12); 2].join("-|-")
+    );
+
+    let starts: Vec<usize> = std::iter::once(0)
+        .chain(cuts.iter().copied().filter(|&cut| cut > 0))
+        .collect();
+    let last_frame = frame_scores.last().map_or(0, |&(frame, _)| frame);
+    for (i, &start) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map_or(last_frame, |next| next - 1);
+        let scores: Vec<f64> = frame_scores
+            .iter()
+            .filter(|(frame, _)| (start..=end).contains(frame))
+            .map(|&(_, score)| score)
+            .collect();
+        if scores.is_empty() {
+            continue;
+        }
+        let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+        let min = scores.iter().copied().fold(f64::INFINITY, f64::min);
+        report!(
+            "{start:>8} | {end:>8} | {:>8} | {mean:>12.8} | {min:>12.8}",
+            scores.len()
+        );
+    }
+}
+
+/// Where to write the frames that score below a threshold, for debugging
+#[derive(Clone)]
+pub struct FrameDump {
+    /// Directory for the source and distorted frames, for `--dump-frames`
+    pub dir: Option<PathBuf>,
+    /// Directory for the difference heatmaps, for `--heatmap`
+    pub heatmap: Option<PathBuf>,
+    pub below: f64,
+}
+
+impl FrameDump {
+    /// Every file written for `frame`
+    fn files(&self, frame: usize) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        if let Some(dir) = &self.dir {
+            files.push(dir.join(format!("frame_{frame}_src.png")));
+            files.push(dir.join(format!("frame_{frame}_dst.png")));
+        }
+        if let Some(heatmap) = &self.heatmap {
+            files.push(heatmap.join(format!("frame_{frame}_heatmap.png")));
+        }
+        files
+    }
+}
+
+/// Options that change how every frame pair is scored
+#[derive(Clone, Copy, Debug)]
+pub struct ScoringOptions {
+    /// Scale the distorted frames to the resolution of the source
+    pub resize: bool,
+    pub downscale: Option<Downscale>,
+    /// Score only edges and fine detail
+    pub edges: bool,
+    /// Score only one in this many pixels of each row
+    pub pixel_stride: Option<usize>,
+    pub difference_space: DifferenceSpace,
+    /// Clip both frames to the gamut of these primaries
+    pub normalize_matrix: Option<ColorPrimaries>,
+    /// Score only the pixels whose source luma is inside this window
+    pub luma_window: Option<LumaWindow>,
+}
+
+/// Everything the workers score frames with, shared by all of them
+struct FrameScorer {
+    src_yuvcfg: YuvConfig,
+    dst_yuvcfg: YuvConfig,
+    options: ScoringOptions,
+    /// Resolution both inputs are scored at, for `--correct-sar`
+    display_size: Option<(u32, u32)>,
+    mask: Option<Mask>,
+    luma_scores: Option<LumaScores>,
+    highlight_weights: Option<HighlightWeights>,
+    dump: Option<FrameDump>,
+}
+
+fn calc_score<S: Pixel, D: Pixel>(
+    frame_idx: usize,
+    src_frame: Frame<S>,
+    dst_frame: Frame<D>,
+    scorer: &FrameScorer,
+) -> Result<f64, Ssimu2Error> {
+    let FrameScorer {
+        src_yuvcfg,
+        dst_yuvcfg,
+        options:
+            ScoringOptions {
+                resize,
+                downscale,
+                edges,
+                pixel_stride,
+                difference_space,
+                normalize_matrix,
+                luma_window,
+            },
+        display_size,
+        ref mask,
+        ref luma_scores,
+        ref highlight_weights,
+        ref dump,
+    } = *scorer;
+    let src_size = (
+        src_frame.planes[0].cfg.width,
+        src_frame.planes[0].cfg.height,
+    );
+    let dst_size = (
+        dst_frame.planes[0].cfg.width,
+        dst_frame.planes[0].cfg.height,
+    );
+    let frame_error = |reason: String| Ssimu2Error::DecodeError {
+        input: format!("frame {frame_idx}"),
+        reason,
+    };
+    let score_error = |e| match e {
+        Ssimulacra2Error::NonMatchingImageDimensions => Ssimu2Error::ResolutionMismatch {
+            src: src_size,
+            dst: dst_size,
+        },
+        e => frame_error(e.to_string()),
     };
 
-    let src_yuv = Yuv::new(src_frame, *src_yuvcfg).unwrap();
-    let dst_yuv = Yuv::new(dst_frame, *dst_yuvcfg).unwrap();
+    if let Some(highlight_weights) = highlight_weights {
+        let weight = highlight_weight(&src_frame, &src_yuvcfg);
+        highlight_weights.lock().unwrap().insert(frame_idx, weight);
+    }
+    let window_mask = luma_window.map(|window| window.mask(&src_frame, &src_yuvcfg));
+
+    let src_yuv = Yuv::new(src_frame, src_yuvcfg).map_err(|e| frame_error(e.to_string()))?;
+    let dst_yuv = Yuv::new(dst_frame, dst_yuvcfg).map_err(|e| frame_error(e.to_string()))?;
+    let score = if resize
+        || is_ictcp(src_yuvcfg.matrix_coefficients)
+        || is_ictcp(dst_yuvcfg.matrix_coefficients)
+        || edges
+        || pixel_stride.is_some()
+        || difference_space != DifferenceSpace::Linear
+        || display_size.is_some()
+        || downscale.is_some()
+        || normalize_matrix.is_some()
+        || mask.is_some()
+        || window_mask.is_some()
+        || luma_scores.is_some()
+    {
+        let mut src_rgb = linearize(&src_yuv).map_err(|e| frame_error(e.to_string()))?;
+        let mut dst_rgb = linearize(&dst_yuv).map_err(|e| frame_error(e.to_string()))?;
+        if let Some((width, height)) = display_size {
+            let to_display = |rgb: LinearRgb| {
+                if (rgb.width(), rgb.height()) == (width as usize, height as usize) {
+                    rgb
+                } else {
+                    Downscale::Size(width, height).apply(rgb)
+                }
+            };
+            src_rgb = to_display(src_rgb);
+            dst_rgb = to_display(dst_rgb);
+        }
+        if resize && (src_rgb.width(), src_rgb.height()) != (dst_rgb.width(), dst_rgb.height()) {
+            dst_rgb =
+                Downscale::Size(src_rgb.width() as u32, src_rgb.height() as u32).apply(dst_rgb);
+        }
+        let (src_rgb, mut dst_rgb) = match normalize_matrix {
+            Some(primaries) => (
+                clip_to_gamut(src_rgb, primaries).map_err(|e| frame_error(e.to_string()))?,
+                clip_to_gamut(dst_rgb, primaries).map_err(|e| frame_error(e.to_string()))?,
+            ),
+            None => (src_rgb, dst_rgb),
+        };
+        if let Some(mask) = mask {
+            mask.apply(&src_rgb, &mut dst_rgb);
+        }
+        if let Some(window_mask) = &window_mask {
+            window_mask.apply(&src_rgb, &mut dst_rgb);
+        }
+        let (src_rgb, dst_rgb) = match downscale {
+            Some(downscale) => (downscale.apply(src_rgb), downscale.apply(dst_rgb)),
+            None => (src_rgb, dst_rgb),
+        };
+        let (src_rgb, dst_rgb) = if edges {
+            (high_pass(&src_rgb), high_pass(&dst_rgb))
+        } else {
+            (src_rgb, dst_rgb)
+        };
+        let (src_rgb, dst_rgb) = match pixel_stride {
+            Some(stride) => (
+                stride_pixels(&src_rgb, stride),
+                stride_pixels(&dst_rgb, stride),
+            ),
+            None => (src_rgb, dst_rgb),
+        };
+        let (src_rgb, dst_rgb) = (
+            difference_space.apply(src_rgb),
+            difference_space.apply(dst_rgb),
+        );
+        if let Some(luma_scores) = luma_scores {
+            // A failed luma pass fails the frame like the full pass would, so that both
+            // stats cover the same frames
+            let score = compute_frame_ssimulacra2(luma_only(&src_rgb), luma_only(&dst_rgb))
+                .map_err(score_error)?;
+            luma_scores.lock().unwrap().insert(frame_idx, score);
+        }
+        compute_frame_ssimulacra2(src_rgb, dst_rgb)
+    } else {
+        compute_frame_ssimulacra2(&src_yuv, &dst_yuv)
+    }
+    .map_err(score_error)?;
+
+    if let Some(dump) = dump.as_ref().filter(|dump| score < dump.below) {
+        if let Some(dir) = &dump.dir {
+            save_frame_png(&src_yuv, &dir.join(format!("frame_{frame_idx}_src.png")))?;
+            save_frame_png(&dst_yuv, &dir.join(format!("frame_{frame_idx}_dst.png")))?;
+        }
+        if let Some(heatmap) = &dump.heatmap {
+            let src_rgb = linearize(&src_yuv).map_err(|e| frame_error(e.to_string()))?;
+            let dst_rgb = linearize(&dst_yuv).map_err(|e| frame_error(e.to_string()))?;
+            write_heatmap(
+                &src_rgb,
+                &dst_rgb,
+                &heatmap.join(format!("frame_{frame_idx}_heatmap.png")),
+            )?;
+        }
+    }
+
+    Ok(score)
+}
+
+/// Resolution a frame of `width`x`height` with sample aspect ratio `num:den` is shown
+/// at. Only the width is scaled, as players do.
+fn display_size((width, height): (usize, usize), (num, den): (u64, u64)) -> (u32, u32) {
+    let width = (width as f64 * num as f64 / den as f64).round().max(1.0);
+    (width as u32, height as u32)
+}
+
+/// Clips a frame to the gamut of `primaries`, for `--normalize-matrix`. The frame stays
+/// in the BT.709 based linear RGB that scoring expects.
+fn clip_to_gamut(rgb: LinearRgb, primaries: ColorPrimaries) -> anyhow::Result<LinearRgb> {
+    let mut rgb = Rgb::try_from((rgb, TransferCharacteristic::Linear, primaries))?;
+    for pixel in rgb.data_mut() {
+        for value in pixel {
+            *value = value.clamp(0.0, 1.0);
+        }
+    }
+    Ok(LinearRgb::try_from(rgb)?)
+}
+
+/// Scores of the luma-only pass for `--plane-stats`, keyed by frame index
+type LumaScores = Mutex<BTreeMap<usize, f64>>;
+
+/// Replaces every pixel with its luminance, so that only luma differences are scored
+fn luma_only(rgb: &LinearRgb) -> LinearRgb {
+    let data = rgb
+        .data()
+        .iter()
+        .map(|&[r, g, b]| {
+            let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            [y, y, y]
+        })
+        .collect();
+    LinearRgb::new(data, rgb.width(), rgb.height()).expect("Data matches the frame dimensions")
+}
+
+/// Replaces every sample with the magnitude of its 4-neighbour Laplacian, for `--edges`.
+/// Flat areas become black and only detail and edges remain, so blur costs far more
+/// than in a regular score. Samples past the border repeat the edge.
+fn high_pass(rgb: &LinearRgb) -> LinearRgb {
+    let (width, height) = (rgb.width(), rgb.height());
+    let data = rgb.data();
+    let at = |x: usize, y: usize| data[y.min(height - 1) * width + x.min(width - 1)];
+    let mut out = Vec::with_capacity(data.len());
+    for y in 0..height {
+        for x in 0..width {
+            let neighbours = [
+                at(x.saturating_sub(1), y),
+                at(x + 1, y),
+                at(x, y.saturating_sub(1)),
+                at(x, y + 1),
+            ];
+            let center = at(x, y);
+            let mut pixel = [0.0; 3];
+            for (c, value) in pixel.iter_mut().enumerate() {
+                let sum: f32 = neighbours.iter().map(|neighbour| neighbour[c]).sum();
+                *value = (4.0 * center[c] - sum).abs().min(1.0);
+            }
+            out.push(pixel);
+        }
+    }
+    LinearRgb::new(out, width, height).expect("Data matches the frame dimensions")
+}
+
+/// Converts a frame to linear RGB, decoding ICtCp itself since the ssimulacra2 crate
+/// can't
+fn linearize<T: Pixel>(yuv: &Yuv<T>) -> anyhow::Result<LinearRgb> {
+    if is_ictcp(yuv.config().matrix_coefficients) {
+        ictcp_to_linear_rgb(yuv)
+    } else {
+        Ok(LinearRgb::try_from(yuv)?)
+    }
+}
+
+/// Converts a frame to sRGB and writes it out as an 8-bit PNG
+fn save_frame_png<T: Pixel>(yuv: &Yuv<T>, path: &Path) -> Result<(), Ssimu2Error> {
+    let rgb = linearize(yuv)
+        .and_then(|lrgb| {
+            Ok(Rgb::try_from((
+                lrgb,
+                TransferCharacteristic::SRGB,
+                ColorPrimaries::BT709,
+            ))?)
+        })
+        .map_err(|e| Ssimu2Error::DecodeError {
+            input: path.to_string_lossy().into_owned(),
+            reason: e.to_string(),
+        })?;
+    let buffer: Vec<u8> = rgb
+        .data()
+        .iter()
+        .flatten()
+        .map(|&v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+    image::save_buffer(
+        path,
+        &buffer,
+        rgb.width() as u32,
+        rgb.height() as u32,
+        ColorType::Rgb8,
+    )
+    .map_err(io::Error::other)?;
+    Ok(())
+}
 
-    Some((
-        frame_idx,
-        compute_frame_ssimulacra2(src_yuv, dst_yuv).expect("Failed to calculate ssimulacra2"),
-    ))
+/// Controls which frames of the inputs are scored
+pub struct FrameSelection {
+    /// The amount of frames to skip at the start
+    pub skip_frames: usize,
+    /// Limit the amount of frames to compare
+    pub frames_to_compare: Option<usize>,
+    /// Distance between uniformly sampled frames
+    pub inc: usize,
+    /// Also score every frame that starts a new scene
+    pub include_scene_changes: bool,
+    /// Score this many randomly chosen frames instead of sampling uniformly
+    pub random_sample: Option<usize>,
+    /// Score only the I frames instead of sampling uniformly
+    pub iframes_only: bool,
+    /// Seed for `random_sample`
+    pub seed: Option<u64>,
+    /// Score exactly the frames shown at these timestamps, ignoring all other options
+    pub timestamps: Vec<Duration>,
+    /// Score only the frames shown between these two timestamps
+    pub range: Option<(Duration, Duration)>,
+    /// Score exactly these frames instead of sampling uniformly
+    pub listed: Option<BTreeSet<usize>>,
+    /// Never score these frames, even if they would otherwise be selected
+    pub exclude: BTreeSet<usize>,
+}
+
+impl FrameSelection {
+    /// Index one past the last frame that may be scored, if limited by `frames_to_compare`
+    fn end_frame(&self) -> Option<usize> {
+        self.frames_to_compare
+            .map(|frames_to_compare| self.skip_frames + (frames_to_compare * self.inc))
+    }
+
+    /// Resolves `timestamps` to frame indices, given the duration of one frame in seconds
+    fn timestamp_frames(&self, frame_duration: f64) -> Vec<(Duration, usize)> {
+        self.timestamps
+            .iter()
+            .map(|&ts| (ts, frame_at(ts, frame_duration)))
+            .collect()
+    }
+
+    /// Turns `range` into `skip_frames` and `frames_to_compare`. Fails if the range
+    /// ends past the end of a video of known length.
+    fn resolve_range(
+        &mut self,
+        frame_count: Option<usize>,
+        frame_duration: f64,
+    ) -> Result<(), Ssimu2Error> {
+        let Some((start, end)) = self.range else {
+            return Ok(());
+        };
+        if let Some(frame_count) = frame_count {
+            let duration = Duration::from_secs_f64(frame_count as f64 * frame_duration);
+            if end > duration {
+                return Err(Ssimu2Error::RangeOutOfBounds {
+                    end: format_timestamp(end),
+                    duration: format_timestamp(duration),
+                });
+            }
+        }
+        let start_frame = frame_at(start, frame_duration);
+        // A range shorter than a frame still scores the frame shown at its start
+        let end_frame = frame_at(end, frame_duration).max(start_frame + 1);
+        self.skip_frames = start_frame;
+        self.frames_to_compare = Some((end_frame - start_frame).div_ceil(self.inc));
+        Ok(())
+    }
+
+    /// Whether [`frame_list`](Self::frame_list) draws frames from the whole length of the
+    /// inputs, which must then be known before scoring starts
+    fn needs_frame_count(&self) -> bool {
+        self.timestamps.is_empty()
+            && self.listed.is_none()
+            && (self.random_sample.is_some() || self.include_scene_changes || self.iframes_only)
+    }
+
+    /// Builds a sorted list of the exact frames to score. Returns `None` when plain
+    /// uniform sampling is enough, which also works for inputs of unknown length.
+    fn frame_list(
+        &self,
+        frame_count: Option<usize>,
+        frame_duration: f64,
+        scene_changes: Option<Vec<usize>>,
+        intra_frames: Option<Vec<usize>>,
+    ) -> Result<Option<Vec<usize>>, Ssimu2Error> {
+        if !self.timestamps.is_empty() {
+            let frames: BTreeSet<usize> = self
+                .timestamp_frames(frame_duration)
+                .into_iter()
+                .map(|(_, frame)| frame)
+                .collect();
+            return Ok(Some(frames.into_iter().collect()));
+        }
+
+        if let Some(listed) = &self.listed {
+            return Ok(Some(listed.iter().copied().collect()));
+        }
+
+        if !self.needs_frame_count() {
+            return Ok(None);
+        }
+
+        let Some(frame_count) = frame_count else {
+            let option = if self.random_sample.is_some() {
+                "--random-sample"
+            } else if self.iframes_only {
+                "--iframes-only"
+            } else {
+                "--include-scene-changes"
+            };
+            return Err(Ssimu2Error::UnknownFrameCount { option });
+        };
+        let end_frame = self
+            .end_frame()
+            .map_or(frame_count, |end_frame| end_frame.min(frame_count));
+        let window = self.skip_frames..end_frame.max(self.skip_frames);
+
+        let mut frames: BTreeSet<usize> = if let Some(intra_frames) = intra_frames {
+            intra_frames
+                .into_iter()
+                .filter(|frame| window.contains(frame))
+                .collect()
+        } else if let Some(sample_size) = self.random_sample {
+            let seed = self.seed.unwrap_or_else(|| {
+                let seed = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos() as u64;
+                note!("Random sample seed: {seed}");
+                seed
+            });
+            let mut rng = StdRng::seed_from_u64(seed);
+            index::sample(&mut rng, window.len(), sample_size.min(window.len()))
+                .into_iter()
+                .map(|i| window.start + i)
+                .collect()
+        } else {
+            window.clone().step_by(self.inc).collect()
+        };
+        frames.extend(
+            scene_changes
+                .into_iter()
+                .flatten()
+                .filter(|frame| window.contains(frame)),
+        );
+        Ok(Some(frames.into_iter().collect()))
+    }
+}
+
+/// Index of the frame shown at `timestamp`, given the duration of one frame in seconds
+fn frame_at(timestamp: Duration, frame_duration: f64) -> usize {
+    // The epsilon keeps timestamps that land exactly on a frame boundary from
+    // rounding down to the previous frame
+    (timestamp.as_secs_f64() / frame_duration + 1e-6) as usize
+}
+
+/// Color settings of one input from the command line. Whatever is left unspecified is
+/// filled in from what the input declares.
+#[derive(Clone, Copy, Debug)]
+pub struct InputColor {
+    pub matrix: MatrixCoefficients,
+    pub transfer: TransferCharacteristic,
+    pub primaries: ColorPrimaries,
+    /// Chroma subsampling to score the input in instead of the decoded one
+    pub subsampling: Option<ChromaSampling>,
+    pub range: ColorRange,
+}
+
+/// Options of a video comparison, built once from the command line
+pub struct CompareOptions {
+    /// Worker threads scoring frames, at most, if `max_memory` allows
+    pub frame_threads: usize,
+    /// Memory budget in MB that limits the frame threads
+    pub max_memory: Option<usize>,
+    /// Decoded frames queued ahead of the workers, twice the frame threads by default
+    pub buffer_frames: Option<usize>,
+    /// Format of an input read from stdin
+    pub piped_format: Option<PipedFormat>,
+    /// Start both inputs on their first keyframe
+    pub align_keyframes: bool,
+    /// Search this many frames for the offset between the inputs
+    pub auto_align: Option<usize>,
+    /// Compare every frame of the source with the next one
+    pub self_temporal: bool,
+    /// Warn if the source looks like it was already lossy encoded
+    pub verify_reference: bool,
+    /// Frame rate to use instead of the one the source declares
+    pub fps: Option<f64>,
+    pub selection: FrameSelection,
+    pub graph: bool,
+    pub graph_size: (u32, u32),
+    pub graph_backend: GraphBackend,
+    pub graph_type: GraphType,
+    pub graph_color: GraphColor,
+    pub graph_output: Option<PathBuf>,
+    pub histogram: bool,
+    /// First frames of the scenes to summarize separately
+    pub scenes: Option<BTreeSet<usize>>,
+    /// Leave the summary to the caller's table
+    pub table: bool,
+    pub oneline: bool,
+    pub percentile_method: PercentileMethod,
+    /// Show 100 minus the score, as a distortion
+    pub invert: bool,
+    /// Print the score of every frame
+    pub verbose: bool,
+    /// Verbose scores per line
+    pub columns: Option<usize>,
+    pub profile: bool,
+    pub thread_stats: bool,
+    pub pin_threads: bool,
+    /// Show the mean of this many last frames in the progress bar
+    pub avg_window: Option<usize>,
+    pub show_current: bool,
+    pub per_frame_stdout: bool,
+    pub emit: Vec<Emit>,
+    pub plane_stats: bool,
+    pub hdr_stats: bool,
+    pub scoring: ScoringOptions,
+    /// Score both inputs at the display resolution of the source
+    pub correct_sar: bool,
+    pub allow_count_mismatch: bool,
+    /// Image that weighs how much each pixel counts
+    pub mask: Option<PathBuf>,
+    /// Stop decoding once a frame scores below this
+    pub stop_below: Option<f64>,
+    pub time_limit: Option<Duration>,
+    /// Frames scored first and left out of the results
+    pub warmup: usize,
+    pub dump: Option<FrameDump>,
+    pub overlay_output: Option<PathBuf>,
+    pub jsonl: Option<PathBuf>,
+    /// Directory for every output, along with a manifest of the run
+    pub out_dir: Option<PathBuf>,
+    pub source_color: InputColor,
+    pub distorted_color: InputColor,
+    /// Fail instead of guessing color properties that nothing declares
+    pub no_guess: bool,
+    pub print_command: bool,
 }
 
-#[allow(clippy::too_many_arguments)]
 pub fn compare_videos(
     source: &str,
     distorted: &str,
-    frame_threads: usize,
-    skip_frames: usize,
-    frames_to_compare: Option<usize>,
-    inc: usize,
-    graph: bool,
-    verbose: bool,
-    src_matrix: MatrixCoefficients,
-    src_transfer: TransferCharacteristic,
-    src_primaries: ColorPrimaries,
-    src_full_range: bool,
-    dst_matrix: MatrixCoefficients,
-    dst_transfer: TransferCharacteristic,
-    dst_primaries: ColorPrimaries,
-    dst_full_range: bool,
-) {
-    if source == "-" || source == "/dev/stdin" {
-        assert!(
-            !(distorted == "-" || distorted == "/dev/stdin"),
-            "Source and distorted inputs cannot both be from piped input"
-        );
-        let distorted = if Path::new(distorted)
-            .extension()
-            .map(|ext| ext.to_ascii_lowercase().to_string_lossy() == "vpy")
-            .unwrap_or(false)
-        {
-            VapoursynthDecoder::new_from_script(Path::new(distorted)).unwrap()
+    mut options: CompareOptions,
+) -> Result<Summary, Ssimu2Error> {
+    if is_piped(source) && is_piped(distorted) {
+        return Err(Ssimu2Error::BothPiped);
+    }
+
+    let manifest = if let Some(out_dir) = options.out_dir.take() {
+        fs::create_dir_all(&out_dir)?;
+        if let Some(dump) = &mut options.dump {
+            dump.dir = dump.dir.as_ref().map(|dir| out_dir.join(dir));
+            dump.heatmap = dump.heatmap.as_ref().map(|dir| out_dir.join(dir));
+        }
+        Some(Manifest::new(out_dir, source, distorted))
+    } else {
+        None
+    };
+    if let Some(dump) = &options.dump {
+        for dir in dump.dir.iter().chain(&dump.heatmap) {
+            fs::create_dir_all(dir)?;
+        }
+    }
+
+    let mask = options
+        .mask
+        .take()
+        .map(|path| {
+            Mask::open(&path).map_err(|e| Ssimu2Error::DecodeError {
+                input: path.to_string_lossy().into_owned(),
+                reason: e.to_string(),
+            })
+        })
+        .transpose()?;
+
+    // A generated pattern takes its geometry and frame rate from the distorted input
+    let (mut source_decoder, mut distorted_decoder) = match parse_pattern(source) {
+        Some(pattern) => {
+            let pattern = pattern.map_err(|reason| Ssimu2Error::DecodeError {
+                input: source.to_string(),
+                reason,
+            })?;
+            let distorted_decoder = VideoInput::open(distorted, options.piped_format)?;
+            let details = distorted_decoder.get_video_details();
+            (
+                VideoInput::Pattern(PatternDecoder::new(pattern, details)),
+                distorted_decoder,
+            )
+        }
+        None => (
+            VideoInput::open(source, options.piped_format)?,
+            VideoInput::open(distorted, options.piped_format)?,
+        ),
+    };
+
+    // Inputs that don't declare a sample aspect ratio have square pixels
+    let sar = options.correct_sar.then(|| {
+        (
+            detect_sar(source).unwrap_or((1, 1)),
+            detect_sar(distorted).unwrap_or((1, 1)),
+        )
+    });
+
+    if options.verify_reference {
+        if is_piped(source) || parse_pattern(source).is_some() {
+            note!("Skipping --verify-reference, the source can only be checked if it is a file");
         } else {
-            VapoursynthDecoder::new_from_video(Path::new(distorted)).unwrap()
+            check_reference(source, options.piped_format)?;
+        }
+    }
+
+    // Drops the frames before each input's first keyframe, so that both start on a GOP
+    let (source_offset, distorted_offset) = if options.align_keyframes {
+        let offset = |input: &str, decoder: &mut VideoInput| {
+            let decode_error = |reason: String| Ssimu2Error::DecodeError {
+                input: input.to_string(),
+                reason,
+            };
+            let keyframe = first_keyframe(input)
+                .map_err(|e| decode_error(e.to_string()))?
+                .unwrap_or_else(|| {
+                    warning!("{input} has no picture types, aligning it from its first frame");
+                    0
+                });
+            if !decoder.skip_frames(keyframe) {
+                return Err(decode_error(format!(
+                    "input ended before its first keyframe at frame {keyframe}"
+                )));
+            }
+            Ok(keyframe)
         };
-        let distorted_frame_count = distorted.get_frame_count().ok();
-        return compare_videos_inner(
-            new_decoder_from_stdin().unwrap(),
-            distorted,
-            None,
-            distorted_frame_count,
-            frame_threads,
-            skip_frames,
-            frames_to_compare,
-            inc,
-            graph,
-            verbose,
-            src_matrix,
-            src_transfer,
-            src_primaries,
-            src_full_range,
-            dst_matrix,
-            dst_transfer,
-            dst_primaries,
-            dst_full_range,
+        let offsets = (
+            offset(source, &mut source_decoder)?,
+            offset(distorted, &mut distorted_decoder)?,
         );
-    }
+        note!(
+            "Aligned on keyframes: skipped {} source and {} distorted frame(s)",
+            offsets.0,
+            offsets.1
+        );
+        offsets
+    } else if let Some(radius) = options.auto_align {
+        let offset = find_alignment(source, distorted, options.piped_format, radius)?;
+        let offsets = (offset.max(0) as usize, (-offset).max(0) as usize);
+        for (input, decoder, skip) in [
+            (source, &mut source_decoder, offsets.0),
+            (distorted, &mut distorted_decoder, offsets.1),
+        ] {
+            if !decoder.skip_frames(skip) {
+                return Err(Ssimu2Error::DecodeError {
+                    input: input.to_string(),
+                    reason: format!("input ended before the aligned start at frame {skip}"),
+                });
+            }
+        }
+        offsets
+    } else if options.self_temporal {
+        // The second decoder of the same input runs one frame ahead
+        if !distorted_decoder.skip_frames(1) {
+            return Err(Ssimu2Error::DecodeError {
+                input: distorted.to_string(),
+                reason: "--self-temporal needs at least two frames".to_string(),
+            });
+        }
+        (0, 1)
+    } else {
+        (0, 0)
+    };
+    // With --self-temporal, the last frame has no next frame to be compared with, so the
+    // source is counted one frame short like the distorted side
+    let source_count_offset = if options.self_temporal {
+        1
+    } else {
+        source_offset
+    };
+    let source_frame_count = source_decoder
+        .frame_count()
+        .map(|count| count.saturating_sub(source_count_offset));
+    let distorted_frame_count = distorted_decoder
+        .frame_count()
+        .map(|count| count.saturating_sub(distorted_offset));
+
+    // y4m files don't declare their length, so it is counted in the background while
+    // scoring starts, and the progress bar turns from a spinner into a bar once it is known.
+    // A frame list drawn from the whole length can't wait for that, so it is counted first.
+    let mut counted_frame_count = None;
+    let lazy_frame_count = if source_frame_count.or(distorted_frame_count).is_none() {
+        [
+            (source, &source_decoder, source_count_offset),
+            (distorted, &distorted_decoder, distorted_offset),
+        ]
+        .into_iter()
+        .find(|(_, decoder, _)| matches!(decoder, VideoInput::Y4mFile(_)))
+        .map(|(input, decoder, offset)| {
+            let (path, details) = (PathBuf::from(input), decoder.get_video_details());
+            let (count_tx, count_rx) = mpsc::channel();
+            if options.selection.needs_frame_count() {
+                let count = count_y4m_frames(&path, &details)
+                    .map_err(|e| Ssimu2Error::DecodeError {
+                        input: input.to_string(),
+                        reason: e.to_string(),
+                    })?
+                    .saturating_sub(offset);
+                counted_frame_count = Some(count);
+                let _ = count_tx.send(count);
+            } else {
+                std::thread::spawn(move || {
+                    if let Ok(count) = count_y4m_frames(&path, &details) {
+                        let _ = count_tx.send(count.saturating_sub(offset));
+                    }
+                });
+            }
+            Ok::<_, Ssimu2Error>(count_rx)
+        })
+        .transpose()?
+    } else {
+        None
+    };
 
-    if distorted == "-" || distorted == "/dev/stdin" {
-        let source = if Path::new(source)
-            .extension()
-            .map(|ext| ext.to_ascii_lowercase().to_string_lossy() == "vpy")
-            .unwrap_or(false)
-        {
-            VapoursynthDecoder::new_from_script(Path::new(source)).unwrap()
+    let frame_threads = if let Some(max_memory) = options.max_memory {
+        let (source_info, distorted_info) = (
+            source_decoder.get_video_details(),
+            distorted_decoder.get_video_details(),
+        );
+        let frame_bytes = frame_memory(&source_info) + frame_memory(&distorted_info);
+        let scoring_bytes = scoring_memory(&source_info) + scoring_memory(&distorted_info);
+        // The default buffer grows with the thread count, a fixed one is paid for up front
+        let (per_thread, buffered) = match options.buffer_frames {
+            Some(buffer_frames) => (frame_bytes + scoring_bytes, buffer_frames * frame_bytes),
+            None => (3 * frame_bytes + scoring_bytes, 0),
+        };
+        let budget = max_memory
+            .saturating_mul(1024 * 1024)
+            .saturating_sub(buffered);
+        let max_threads = (budget / per_thread).max(1);
+        let frame_threads = options.frame_threads.min(max_threads);
+        note!(
+            "Using {frame_threads} frame thread(s) to stay within {max_memory} MB \
+             (~{} MB per thread)",
+            per_thread.div_ceil(1024 * 1024)
+        );
+        frame_threads
+    } else {
+        options.frame_threads
+    };
+    let buffer_frames = options.buffer_frames.unwrap_or(2 * frame_threads).max(1);
+
+    // Scene detection needs random access, so it always runs on an input that is a file
+    let scene_changes = if options.selection.include_scene_changes {
+        let (input, offset) = if is_piped(source) || parse_pattern(source).is_some() {
+            (distorted, distorted_offset)
         } else {
-            VapoursynthDecoder::new_from_video(Path::new(source)).unwrap()
+            (source, source_offset)
         };
-        let source_frame_count = source.get_frame_count().ok();
-        return compare_videos_inner(
-            source,
-            new_decoder_from_stdin().unwrap(),
+        let scene_changes = detect_scene_changes(input).map_err(|e| Ssimu2Error::DecodeError {
+            input: input.to_string(),
+            reason: e.to_string(),
+        })?;
+        // Scene changes are found on the whole input, but frames are counted after alignment
+        Some(
+            scene_changes
+                .into_iter()
+                .filter_map(|frame| frame.checked_sub(offset))
+                .collect(),
+        )
+    } else {
+        None
+    };
+    let intra_frames = if options.selection.iframes_only {
+        let (input, offset) = if is_piped(source) || parse_pattern(source).is_some() {
+            (distorted, distorted_offset)
+        } else {
+            (source, source_offset)
+        };
+        let decode_error = |reason: String| Ssimu2Error::DecodeError {
+            input: input.to_string(),
+            reason,
+        };
+        let intra_frames = intra_frames(input)
+            .map_err(|e| decode_error(e.to_string()))?
+            .ok_or_else(|| {
+                decode_error("--iframes-only needs an input with picture types".to_string())
+            })?;
+        Some(
+            intra_frames
+                .into_iter()
+                .filter_map(|frame| frame.checked_sub(offset))
+                .collect(),
+        )
+    } else {
+        None
+    };
+    // Every conversion between time and frames uses the same frame duration
+    let frame_duration = match options.fps {
+        Some(fps) => 1.0 / fps,
+        None => source_decoder.get_video_details().time_base.as_f64(),
+    };
+    if !(frame_duration.is_finite() && frame_duration > 0.0) {
+        let option = if options.selection.range.is_some() {
+            Some("--range")
+        } else if !options.selection.timestamps.is_empty() {
+            Some("--at")
+        } else {
+            None
+        };
+        if let Some(option) = option {
+            return Err(Ssimu2Error::MissingFrameRate {
+                option,
+                input: source.to_string(),
+            });
+        }
+    }
+    options
+        .selection
+        .resolve_range(source_frame_count.or(distorted_frame_count), frame_duration)?;
+    let frame_list = options.selection.frame_list(
+        source_frame_count
+            .or(distorted_frame_count)
+            .or(counted_frame_count),
+        frame_duration,
+        scene_changes,
+        intra_frames,
+    )?;
+
+    let source_range = apply_declared_color(source, &mut options.source_color, options.no_guess)?;
+    let distorted_range =
+        apply_declared_color(distorted, &mut options.distorted_color, options.no_guess)?;
+
+    compare_videos_inner(
+        OpenedInputs {
+            source: source_decoder,
+            distorted: distorted_decoder,
+            source_name: source,
+            distorted_name: distorted,
             source_frame_count,
-            None,
+            distorted_frame_count,
+            lazy_frame_count,
+        },
+        ResolvedRun {
             frame_threads,
-            skip_frames,
-            frames_to_compare,
-            inc,
-            graph,
-            verbose,
-            src_matrix,
-            src_transfer,
-            src_primaries,
-            src_full_range,
-            dst_matrix,
-            dst_transfer,
-            dst_primaries,
-            dst_full_range,
-        );
+            buffer_frames,
+            frame_list,
+            frame_duration,
+            sar,
+            mask,
+            manifest,
+            source_range,
+            distorted_range,
+        },
+        options,
+    )
+}
+
+/// How many frames from the start of the source `--verify-reference` checks
+const REFERENCE_CHECK_FRAMES: usize = 10;
+/// Standard deviation of the blur `--verify-reference` applies, in pixels
+const REFERENCE_CHECK_SIGMA: f32 = 0.8;
+/// Sources that still score above this against a blurred copy of themselves have little
+/// fine detail left to lose, which is typical of lossy encodes
+const REFERENCE_CHECK_THRESHOLD: f64 = 85.0;
+/// Frames with a smaller luminance standard deviation are too flat to tell anything
+const REFERENCE_CHECK_MIN_DEVIATION: f32 = 0.01;
+
+/// Checks for `--verify-reference` whether the source looks like it was already lossy
+/// encoded, and warns if so. A pristine source has fine detail that a slight blur
+/// destroys, so it scores noticeably lower against a blurred copy of itself than a
+/// source that was already smoothed by compression. This is a heuristic: naturally soft
+/// or noise-free content can trigger it too.
+///
+/// The source is opened separately for the check, so it must be a file.
+fn check_reference(source: &str, piped_format: Option<PipedFormat>) -> Result<(), Ssimu2Error> {
+    let mut decoder = VideoInput::open(source, piped_format)?;
+    let config = alignment_config(source, &decoder.get_video_details());
+    let scores = if config.bit_depth > 8 {
+        blurred_self_scores::<u16>(&mut decoder, config)
+    } else {
+        blurred_self_scores::<u8>(&mut decoder, config)
+    }?;
+    if scores.is_empty() {
+        note!("Skipping --verify-reference, the first frames of {source} are too flat to check");
+        return Ok(());
     }
 
-    let source = if Path::new(source)
-        .extension()
-        .map(|ext| ext.to_ascii_lowercase().to_string_lossy() == "vpy")
-        .unwrap_or(false)
-    {
-        VapoursynthDecoder::new_from_script(Path::new(source)).unwrap()
+    let mut data = Data::new(scores);
+    let median = data.median();
+    if median > REFERENCE_CHECK_THRESHOLD {
+        warning!(
+            "{source} may already be lossy compressed: it scores {median:.2} against a \
+             blurred copy of itself, while pristine sources usually score below \
+             {REFERENCE_CHECK_THRESHOLD}. Make sure it is the original and not an encode."
+        );
     } else {
-        VapoursynthDecoder::new_from_video(Path::new(source)).unwrap()
+        note!("{source} passed the reference check ({median:.2} against a blurred copy)");
+    }
+    Ok(())
+}
+
+/// Scores the first frames of an input against a blurred copy of themselves, leaving
+/// out flat frames
+fn blurred_self_scores<T: Pixel>(
+    decoder: &mut VideoInput,
+    config: YuvConfig,
+) -> Result<Vec<f64>, Ssimu2Error> {
+    let frame_error = |reason: String| Ssimu2Error::DecodeError {
+        input: "reference check".to_string(),
+        reason,
+    };
+    let mut scores = Vec::new();
+    for _ in 0..REFERENCE_CHECK_FRAMES {
+        let Some(frame) = decoder.read_video_frame::<T>() else {
+            break;
+        };
+        let yuv = Yuv::new(frame, config).map_err(|e| frame_error(e.to_string()))?;
+        let rgb = linearize(&yuv).map_err(|e| frame_error(e.to_string()))?;
+
+        let luma: Vec<f32> = rgb
+            .data()
+            .iter()
+            .map(|&[r, g, b]| 0.2126 * r + 0.7152 * g + 0.0722 * b)
+            .collect();
+        let mean = luma.iter().sum::<f32>() / luma.len() as f32;
+        let variance = luma.iter().map(|y| (y - mean).powi(2)).sum::<f32>() / luma.len() as f32;
+        if variance.sqrt() < REFERENCE_CHECK_MIN_DEVIATION {
+            continue;
+        }
+
+        let (width, height) = (rgb.width(), rgb.height());
+        let buffer = ImageBuffer::<image::Rgb<f32>, _>::from_raw(
+            width as u32,
+            height as u32,
+            rgb.data().iter().flatten().copied().collect::<Vec<_>>(),
+        )
+        .expect("Frame data matches the frame dimensions");
+        let blurred = LinearRgb::new(
+            imageops::blur(&buffer, REFERENCE_CHECK_SIGMA)
+                .into_raw()
+                .chunks_exact(3)
+                .map(|chunk| [chunk[0], chunk[1], chunk[2]])
+                .collect(),
+            width,
+            height,
+        )
+        .expect("Blurred data matches the frame dimensions");
+        scores
+            .push(compute_frame_ssimulacra2(rgb, blurred).map_err(|e| frame_error(e.to_string()))?);
+    }
+    Ok(scores)
+}
+
+/// Finds the temporal offset between the inputs for `--auto-align`, by scoring the first
+/// distorted frame against the first `radius` + 1 source frames, and the first source
+/// frame against the first `radius` + 1 distorted frames. A positive offset means the
+/// distorted input starts that many frames into the source, a negative one that it has
+/// that many extra frames at its start.
+///
+/// The inputs are opened separately for the search, so they must be files.
+fn find_alignment(
+    source: &str,
+    distorted: &str,
+    piped_format: Option<PipedFormat>,
+    radius: usize,
+) -> Result<isize, Ssimu2Error> {
+    for input in [source, distorted] {
+        if is_piped(input) || parse_pattern(input).is_some() {
+            return Err(Ssimu2Error::DecodeError {
+                input: input.to_string(),
+                reason: "--auto-align needs both inputs to be files".to_string(),
+            });
+        }
+    }
+    let mut source_decoder = VideoInput::open(source, piped_format)?;
+    let mut distorted_decoder = VideoInput::open(distorted, piped_format)?;
+    let src_config = alignment_config(source, &source_decoder.get_video_details());
+    let dst_config = alignment_config(distorted, &distorted_decoder.get_video_details());
+
+    let candidates = match (src_config.bit_depth > 8, dst_config.bit_depth > 8) {
+        (false, false) => alignment_scores::<u8, u8>(
+            &mut source_decoder,
+            &mut distorted_decoder,
+            src_config,
+            dst_config,
+            radius,
+        ),
+        (false, true) => alignment_scores::<u8, u16>(
+            &mut source_decoder,
+            &mut distorted_decoder,
+            src_config,
+            dst_config,
+            radius,
+        ),
+        (true, false) => alignment_scores::<u16, u8>(
+            &mut source_decoder,
+            &mut distorted_decoder,
+            src_config,
+            dst_config,
+            radius,
+        ),
+        (true, true) => alignment_scores::<u16, u16>(
+            &mut source_decoder,
+            &mut distorted_decoder,
+            src_config,
+            dst_config,
+            radius,
+        ),
+    }?;
+    let (offset, score) = candidates
+        .into_iter()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .ok_or_else(|| Ssimu2Error::DecodeError {
+            input: distorted.to_string(),
+            reason: "no frames to align".to_string(),
+        })?;
+    note!(
+        "Auto-aligned with an offset of {offset} frame(s) (score {score:.8}): skipping {} \
+         source and {} distorted frame(s)",
+        offset.max(0),
+        (-offset).max(0)
+    );
+    Ok(offset)
+}
+
+/// Color configuration for scoring alignment candidates. Only the range matters for
+/// finding the best match, everything else is left for `Yuv` to guess.
+fn alignment_config(input: &str, details: &VideoDetails) -> YuvConfig {
+    let (ss_x, ss_y) = details.chroma_sampling.get_decimation().unwrap_or((0, 0));
+    YuvConfig {
+        bit_depth: details.bit_depth as u8,
+        subsampling_x: ss_x as u8,
+        subsampling_y: ss_y as u8,
+        full_range: detect_color(input).full_range.unwrap_or(false),
+        matrix_coefficients: MatrixCoefficients::Unspecified,
+        transfer_characteristics: TransferCharacteristic::Unspecified,
+        color_primaries: ColorPrimaries::Unspecified,
+    }
+}
+
+/// Scores every candidate offset of [`find_alignment`] on the first frames of the inputs
+fn alignment_scores<S: Pixel, D: Pixel>(
+    source: &mut VideoInput,
+    distorted: &mut VideoInput,
+    src_config: YuvConfig,
+    dst_config: YuvConfig,
+    radius: usize,
+) -> Result<Vec<(isize, f64)>, Ssimu2Error> {
+    let src_frames: Vec<Frame<S>> = (0..=radius)
+        .map_while(|_| source.read_video_frame())
+        .collect();
+    let dst_frames: Vec<Frame<D>> = (0..=radius)
+        .map_while(|_| distorted.read_video_frame())
+        .collect();
+    let score = |src: &Frame<S>, dst: &Frame<D>| -> Result<f64, Ssimu2Error> {
+        let frame_error = |reason: String| Ssimu2Error::DecodeError {
+            input: "alignment".to_string(),
+            reason,
+        };
+        let src = Yuv::new(src.clone(), src_config).map_err(|e| frame_error(e.to_string()))?;
+        let dst = Yuv::new(dst.clone(), dst_config).map_err(|e| frame_error(e.to_string()))?;
+        // Scaled like `--resize` would, a resolution mismatch is reported by the full run
+        let src = linearize(&src).map_err(|e| frame_error(e.to_string()))?;
+        let mut dst = linearize(&dst).map_err(|e| frame_error(e.to_string()))?;
+        if (src.width(), src.height()) != (dst.width(), dst.height()) {
+            dst = Downscale::Size(src.width() as u32, src.height() as u32).apply(dst);
+        }
+        compute_frame_ssimulacra2(src, dst).map_err(|e| frame_error(e.to_string()))
     };
-    let distorted = if Path::new(distorted)
-        .extension()
-        .map(|ext| ext.to_ascii_lowercase().to_string_lossy() == "vpy")
-        .unwrap_or(false)
+
+    let mut candidates = Vec::new();
+    if let Some(first_dst) = dst_frames.first() {
+        for (offset, src) in src_frames.iter().enumerate() {
+            candidates.push((offset as isize, score(src, first_dst)?));
+        }
+    }
+    if let Some(first_src) = src_frames.first() {
+        for (offset, dst) in dst_frames.iter().enumerate().skip(1) {
+            candidates.push((-(offset as isize), score(first_src, dst)?));
+        }
+    }
+    Ok(candidates)
+}
+
+/// Fills in whatever the command line left unspecified with the values `input` declares,
+/// and returns whether it is full range, along with where the range came from. Anything
+/// still unspecified afterwards is guessed from the resolution, unless `no_guess` is set,
+/// which makes it an error instead.
+fn apply_declared_color(
+    input: &str,
+    color: &mut InputColor,
+    no_guess: bool,
+) -> Result<(bool, RangeOrigin), Ssimu2Error> {
+    let InputColor {
+        matrix,
+        transfer,
+        primaries,
+        range,
+        ..
+    } = color;
+    let range = *range;
+    // Opening the input again is only worth it if something is left to fill in
+    let declared = if *matrix == MatrixCoefficients::Unspecified
+        || *transfer == TransferCharacteristic::Unspecified
+        || *primaries == ColorPrimaries::Unspecified
+        || range == ColorRange::Auto
     {
-        VapoursynthDecoder::new_from_script(Path::new(distorted)).unwrap()
+        detect_color(input)
     } else {
-        VapoursynthDecoder::new_from_video(Path::new(distorted)).unwrap()
+        DeclaredColor::default()
     };
-    let source_frame_count = source.get_frame_count().ok();
-    let distorted_frame_count = distorted.get_frame_count().ok();
-    compare_videos_inner(
+    if *matrix == MatrixCoefficients::Unspecified {
+        *matrix = declared.matrix.unwrap_or(*matrix);
+    }
+    if *transfer == TransferCharacteristic::Unspecified {
+        *transfer = declared.transfer.unwrap_or(*transfer);
+    }
+    if *primaries == ColorPrimaries::Unspecified {
+        *primaries = declared.primaries.unwrap_or(*primaries);
+    }
+    if no_guess {
+        let undeclared = [
+            (
+                *matrix == MatrixCoefficients::Unspecified,
+                "matrix coefficients",
+            ),
+            (
+                *transfer == TransferCharacteristic::Unspecified,
+                "transfer characteristics",
+            ),
+            (*primaries == ColorPrimaries::Unspecified, "color primaries"),
+            (
+                range == ColorRange::Auto && declared.full_range.is_none(),
+                "color range",
+            ),
+        ];
+        if let Some((_, property)) = undeclared.into_iter().find(|&(missing, _)| missing) {
+            return Err(Ssimu2Error::UndeclaredColor {
+                input: input.to_string(),
+                property,
+            });
+        }
+    }
+    Ok(range.resolve(input, declared.full_range))
+}
+
+/// Number of bytes of a single decoded frame of an input
+fn frame_memory(details: &VideoDetails) -> usize {
+    let pixels = details.width * details.height;
+    let bytes_per_sample = if details.bit_depth > 8 { 2 } else { 1 };
+    let chroma_pixels = match details.chroma_sampling.get_decimation() {
+        Some((ss_x, ss_y)) => 2 * (pixels >> (ss_x + ss_y)),
+        None => 0,
+    };
+    (pixels + chroma_pixels) * bytes_per_sample
+}
+
+/// Rough number of bytes of the floating point planes `compute_frame_ssimulacra2`
+/// allocates while scoring a frame of an input
+fn scoring_memory(details: &VideoDetails) -> usize {
+    // Linear RGB, XYB and the blurred intermediates, in f32, across all scales
+    const SCORING_BYTES_PER_PIXEL: usize = 3 * 4 * 12;
+
+    details.width * details.height * SCORING_BYTES_PER_PIXEL
+}
+
+/// Runs VapourSynth's `misc.SCDetect` over `input` and returns the indices of frames
+/// that start a new scene.
+fn detect_scene_changes(input: &str) -> anyhow::Result<Vec<usize>> {
+    let env = vapoursynth_environment(Path::new(input))?;
+    let core = env.get_core()?;
+    let (node, _) = env.get_output(0)?;
+
+    let misc = core
+        .get_plugin_by_namespace("misc")?
+        .ok_or_else(|| anyhow!("The VapourSynth misc plugin is required for scene detection"))?;
+    let mut args = OwnedMap::new(API::get().ok_or_else(|| anyhow!("VapourSynth API not found"))?);
+    args.set_node("clip", &node)?;
+    let detected = misc.invoke("SCDetect", &args)?;
+    if let Some(err) = detected.error() {
+        bail!("SCDetect failed: {}", err);
+    }
+    let node = detected.get_node("clip")?;
+
+    let mut scene_changes = Vec::new();
+    for i in 0..node.info().num_frames {
+        let frame = node.get_frame(i).map_err(|e| anyhow!("{}", e))?;
+        if frame.props().get_int("_SceneChangePrev").unwrap_or(0) != 0 {
+            scene_changes.push(i);
+        }
+    }
+    Ok(scene_changes)
+}
+
+/// The inputs of a comparison, opened and aligned
+struct OpenedInputs<'a> {
+    source: VideoInput,
+    distorted: VideoInput,
+    source_name: &'a str,
+    distorted_name: &'a str,
+    source_frame_count: Option<usize>,
+    distorted_frame_count: Option<usize>,
+    /// Receives the frame count of an input that is still being counted
+    lazy_frame_count: Option<mpsc::Receiver<usize>>,
+}
+
+/// What [`compare_videos`] works out from its options and the opened inputs
+struct ResolvedRun {
+    /// Frame threads after the `--max-memory` limit
+    frame_threads: usize,
+    buffer_frames: usize,
+    frame_list: Option<Vec<usize>>,
+    frame_duration: f64,
+    /// Sample aspect ratios of the source and distorted inputs, for `--correct-sar`
+    sar: Option<((u64, u64), (u64, u64))>,
+    mask: Option<Mask>,
+    manifest: Option<Manifest>,
+    /// Whether each input is full range, and where that came from
+    source_range: (bool, RangeOrigin),
+    distorted_range: (bool, RangeOrigin),
+}
+
+fn compare_videos_inner(
+    inputs: OpenedInputs,
+    run: ResolvedRun,
+    options: CompareOptions,
+) -> Result<Summary, Ssimu2Error> {
+    let OpenedInputs {
         source,
         distorted,
+        source_name,
+        distorted_name,
         source_frame_count,
         distorted_frame_count,
+        lazy_frame_count,
+    } = inputs;
+    let ResolvedRun {
         frame_threads,
-        skip_frames,
-        frames_to_compare,
-        inc,
+        buffer_frames,
+        frame_list,
+        frame_duration,
+        sar,
+        mask,
+        mut manifest,
+        source_range: (src_full_range, src_range_origin),
+        distorted_range: (dst_full_range, dst_range_origin),
+    } = run;
+    let CompareOptions {
+        self_temporal,
+        selection,
         graph,
+        graph_size,
+        graph_backend,
+        graph_type,
+        graph_color,
+        graph_output,
+        histogram,
+        scenes,
+        table,
+        oneline,
+        percentile_method,
+        invert,
         verbose,
-        src_matrix,
-        src_transfer,
-        src_primaries,
-        src_full_range,
-        dst_matrix,
-        dst_transfer,
-        dst_primaries,
-        dst_full_range,
-    )
-}
-
-#[allow(clippy::too_many_arguments)]
-fn compare_videos_inner<D: Decoder + 'static, E: Decoder + 'static>(
-    source: D,
-    distorted: E,
-    source_frame_count: Option<usize>,
-    distorted_frame_count: Option<usize>,
-    frame_threads: usize,
-    skip_frames: usize,
-    frames_to_compare: Option<usize>,
-    inc: usize,
-    graph: bool,
-    verbose: bool,
-    mut src_matrix: MatrixCoefficients,
-    mut src_transfer: TransferCharacteristic,
-    mut src_primaries: ColorPrimaries,
-    src_full_range: bool,
-    mut dst_matrix: MatrixCoefficients,
-    mut dst_transfer: TransferCharacteristic,
-    mut dst_primaries: ColorPrimaries,
-    dst_full_range: bool,
-) {
-    if let Some(source_frame_count) = source_frame_count {
-        if let Some(distorted_frame_count) = distorted_frame_count {
-            if source_frame_count != distorted_frame_count {
-                eprintln!("WARNING: Frame count mismatch detected, scores may be inaccurate");
-            }
+        columns,
+        profile,
+        thread_stats,
+        pin_threads,
+        avg_window,
+        show_current,
+        per_frame_stdout,
+        emit,
+        plane_stats,
+        hdr_stats,
+        scoring,
+        allow_count_mismatch,
+        stop_below,
+        time_limit,
+        warmup,
+        dump,
+        overlay_output,
+        jsonl,
+        source_color:
+            InputColor {
+                matrix: mut src_matrix,
+                transfer: mut src_transfer,
+                primaries: mut src_primaries,
+                subsampling: src_subsampling,
+                ..
+            },
+        distorted_color:
+            InputColor {
+                matrix: mut dst_matrix,
+                transfer: mut dst_transfer,
+                primaries: mut dst_primaries,
+                subsampling: dst_subsampling,
+                ..
+            },
+        print_command,
+        ..
+    } = options;
+    let ScoringOptions {
+        resize,
+        downscale,
+        edges,
+        pixel_stride,
+        difference_space,
+        normalize_matrix,
+        luma_window,
+    } = scoring;
+    if let (Some(src), Some(dst)) = (source_frame_count, distorted_frame_count) {
+        if src != dst && !allow_count_mismatch {
+            warning!(
+                "{}, scores may be inaccurate",
+                Ssimu2Error::FrameCountMismatch { src, dst }
+            );
         }
     }
 
@@ -353,17 +2126,94 @@ fn compare_videos_inner<D: Decoder + 'static, E: Decoder + 'static>(
     if src_primaries == ColorPrimaries::Unspecified {
         src_primaries = guess_color_primaries(src_matrix, source_info.width, source_info.height);
     }
-    if dst_primaries == ColorPrimaries::Unspecified {
-        dst_primaries =
-            guess_color_primaries(dst_matrix, distorted_info.width, distorted_info.height);
+    if dst_primaries == ColorPrimaries::Unspecified {
+        dst_primaries =
+            guess_color_primaries(dst_matrix, distorted_info.width, distorted_info.height);
+    }
+    if is_ictcp(src_matrix) || is_ictcp(dst_matrix) {
+        warning!(
+            "Decoding ICtCp is experimental and hasn't been validated against a reference, \
+             treat these scores with caution"
+        );
+    }
+
+    let src_size = (source_info.width, source_info.height);
+    let dst_size = (distorted_info.width, distorted_info.height);
+    let display_size = sar.map(|(src_sar, dst_sar)| {
+        let size = display_size(src_size, src_sar);
+        note!(
+            "Scoring both inputs at the display resolution of the source, {}x{} \
+             (source SAR {}:{}, distorted SAR {}:{})",
+            size.0,
+            size.1,
+            src_sar.0,
+            src_sar.1,
+            dst_sar.0,
+            dst_sar.1
+        );
+        size
+    });
+    if src_size != dst_size && display_size.is_none() {
+        if !resize {
+            return Err(Ssimu2Error::ResolutionMismatch {
+                src: src_size,
+                dst: dst_size,
+            });
+        }
+        warning!(
+            "Resizing the distorted video from {}x{} to {}x{}",
+            dst_size.0,
+            dst_size.1,
+            src_size.0,
+            src_size.1
+        );
+    }
+
+    if let Some(mask) = &mask {
+        let (width, height) = mask.dimensions();
+        if (width, height) != (source_info.width, source_info.height) {
+            return Err(Ssimu2Error::DecodeError {
+                input: "mask".to_string(),
+                reason: format!(
+                    "mask is {width}x{height}, but the source is {}x{}",
+                    source_info.width, source_info.height
+                ),
+            });
+        }
+    }
+
+    if let Some(downscale) = downscale {
+        let (width, height) = downscale.target_size(source_info.width, source_info.height);
+        warning!(
+            "Scoring frames downscaled to {width}x{height}. SSIMULACRA2 is scale \
+             sensitive, so these scores are NOT comparable to scores at the native resolution."
+        );
+    }
+    if edges {
+        warning!(
+            "Scoring only edges and fine detail (--edges). This is a non-standard score, \
+             NOT comparable to regular SSIMULACRA2 scores."
+        );
+    }
+    if let Some(stride) = pixel_stride {
+        warning!(
+            "Scoring only one in {stride} pixels of each row (--pixel-stride). This is a \
+             rough estimate, NOT a standard SSIMULACRA2 score."
+        );
+    }
+    if difference_space != DifferenceSpace::Linear {
+        warning!(
+            "Comparing gamma encoded frames (--difference-space gamma). This is a \
+             non-standard score, NOT comparable to regular SSIMULACRA2 scores."
+        );
     }
 
-    let src_ss = source_info
-        .chroma_sampling
+    let src_ss = src_subsampling
+        .unwrap_or(source_info.chroma_sampling)
         .get_decimation()
         .unwrap_or((0, 0));
-    let dist_ss = distorted_info
-        .chroma_sampling
+    let dist_ss = dst_subsampling
+        .unwrap_or(distorted_info.chroma_sampling)
         .get_decimation()
         .unwrap_or((0, 0));
     let src_config = YuvConfig {
@@ -384,86 +2234,212 @@ fn compare_videos_inner<D: Decoder + 'static, E: Decoder + 'static>(
         transfer_characteristics: dst_transfer,
         color_primaries: dst_primaries,
     };
+    let source_subsampling = src_subsampling.map(|subsampling| SubsamplingOverride {
+        subsampling,
+        bit_depth: source_info.bit_depth,
+    });
+    let distorted_subsampling = dst_subsampling.map(|subsampling| SubsamplingOverride {
+        subsampling,
+        bit_depth: distorted_info.bit_depth,
+    });
+    if print_command {
+        // Non-UTF-8 arguments, such as some file names, are shown lossily
+        let args: Vec<String> = std::env::args_os()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        report!(
+            "Command: {}",
+            resolved_command(&args, &src_config, &dst_config, self_temporal)
+        );
+        report!();
+    }
 
     let (result_tx, result_rx) = mpsc::channel();
     let src_bd = src_config.bit_depth;
     let dst_bd = dst_config.bit_depth;
 
-    let current_frame = 0usize;
-    let end_frame = frames_to_compare
-        .map(|frames_to_compare| skip_frames + (frames_to_compare * inc));
-
-    let video_compare = Arc::new(
-        Mutex::new(
-            VideoCompare {
-                current_frame,
-                next_frame: skip_frames,
-                source,
-                distorted,
+    let frames_in_list = frame_list.as_ref().map(|frame_list| {
+        frame_list
+            .iter()
+            .filter(|frame| !selection.exclude.contains(frame))
+            .count()
+    });
+    let frames: FrameIter = match (frame_list, selection.end_frame()) {
+        (Some(frame_list), _) => Box::new(frame_list.into_iter()),
+        (None, Some(end_frame)) => {
+            Box::new((selection.skip_frames..end_frame).step_by(selection.inc))
+        }
+        (None, None) => Box::new((selection.skip_frames..).step_by(selection.inc)),
+    };
+    // Excluded frames are still decoded to keep both inputs aligned, they just aren't scored
+    let excluded = Arc::new(AtomicUsize::new(0));
+    let frames: FrameIter = if selection.exclude.is_empty() {
+        frames
+    } else {
+        let exclude = selection.exclude.clone();
+        let excluded = Arc::clone(&excluded);
+        Box::new(frames.filter(move |frame| {
+            let skip = exclude.contains(frame);
+            if skip {
+                excluded.fetch_add(1, Ordering::Relaxed);
             }
-        )
-    );
+            !skip
+        }))
+    };
+    // The first selected frames are the warmup, whichever order the workers finish them in
+    let mut frames = frames;
+    let mut warmup_frames: BTreeSet<usize> = frames.by_ref().take(warmup).collect();
+    let frames: FrameIter = Box::new(warmup_frames.clone().into_iter().chain(frames));
 
-    for _ in 0..frame_threads {
-        let video_compare = Arc::clone(&video_compare);
-        let result_tx = result_tx.clone();
+    // Set once a frame scores below `stop_below`, so no further frames get decoded
+    let stop = Arc::new(AtomicBool::new(false));
 
-        std::thread::spawn(move || {
-            loop {
-                let score = match (src_bd, dst_bd) {
-                    (8, 8) => calc_score::<u8, u8, _, _>(
-                        &video_compare,
-                        &src_config,
-                        &dst_config,
-                        inc,
-                        end_frame,
-                        verbose,
-                    ),
-                    (8, _) => calc_score::<u8, u16, _, _>(
-                        &video_compare,
-                        &src_config,
-                        &dst_config,
-                        inc,
-                        end_frame,
-                        verbose,
-                    ),
-                    (_, 8) => calc_score::<u16, u8, _, _>(
-                        &video_compare,
-                        &src_config,
-                        &dst_config,
-                        inc,
-                        end_frame,
-                        verbose,
-                    ),
-                    (_, _) => calc_score::<u16, u16, _, _>(
-                        &video_compare,
-                        &src_config,
-                        &dst_config,
-                        inc,
-                        end_frame,
-                        verbose,
-                    ),
-                };
+    let mut outputs = StagedOutputs::default();
+    let overlay_output = overlay_output.map(|path| match &manifest {
+        Some(manifest) => manifest.dir().join(path),
+        None => path,
+    });
+    let overlay = overlay_output
+        .as_deref()
+        .map(|path| {
+            // The overlay is written in the layout the distorted frames are scored in
+            let details = VideoDetails {
+                chroma_sampling: distorted_subsampling
+                    .map_or(distorted_info.chroma_sampling, SubsamplingOverride::layout),
+                ..distorted_info
+            };
+            OverlayWriter::create(&outputs.stage(path), &details)
+        })
+        .transpose()?;
 
-                if let Some(result) = score {
-                    result_tx.send(result).unwrap();
-                } else {
-                    // no score = no more frames to read
-                    break;
-                }
+    // Per-frame scores are streamed as they arrive, so they are not staged like other outputs
+    let jsonl_stdout = jsonl.as_deref().is_some_and(is_piped_output);
+    let jsonl_path = jsonl
+        .filter(|path| !is_piped_output(path))
+        .map(|path| match &manifest {
+            Some(manifest) => manifest.dir().join(path),
+            None => path,
+        });
+    let mut jsonl = match (&jsonl_path, jsonl_stdout) {
+        (Some(path), _) => Some(FrameOutput::create(path)?),
+        (None, true) => Some(FrameOutput::Stdout(io::stdout())),
+        (None, false) => None,
+    };
+
+    let timings = profile.then(|| Arc::new(Timings::default()));
+    let thread_stats = thread_stats.then(|| {
+        Arc::new(
+            (0..frame_threads)
+                .map(|_| ThreadStats::default())
+                .collect::<Vec<_>>(),
+        )
+    });
+    let worker_cores = if pin_threads {
+        match worker_cores(frame_threads) {
+            Some(cores) => {
+                note!(
+                    "Pinning {frame_threads} worker thread(s) to {} core(s)",
+                    cores.len()
+                );
+                Some(Arc::new(cores))
+            }
+            None => {
+                warning!(
+                    "Thread affinity isn't supported on this platform, ignoring --pin-threads"
+                );
+                None
             }
+        }
+    } else {
+        None
+    };
+    let luma_scores = plane_stats.then(LumaScores::default);
+    let highlight_weights = if hdr_stats && !is_hdr(src_transfer) {
+        warning!("--hdr-stats needs a PQ or HLG source, ignoring it");
+        None
+    } else {
+        hdr_stats.then(HighlightWeights::default)
+    };
+
+    // Stops decoding new frames once the time limit is up; frames already queued still finish
+    let time_limit_reached = Arc::new(AtomicBool::new(false));
+    if let Some(time_limit) = time_limit {
+        let stop = Arc::clone(&stop);
+        let time_limit_reached = Arc::clone(&time_limit_reached);
+        std::thread::spawn(move || {
+            std::thread::sleep(time_limit);
+            time_limit_reached.store(true, Ordering::Relaxed);
+            stop.store(true, Ordering::Relaxed);
         });
     }
 
+    install_interrupt_handler();
+
+    let video_compare = VideoCompare {
+        decoder_pos: 0,
+        frames,
+        source,
+        distorted,
+        source_name: source_name.to_string(),
+        distorted_name: distorted_name.to_string(),
+        source_subsampling,
+        distorted_subsampling,
+    };
+
+    let workers = Workers {
+        frame_threads,
+        buffer_frames,
+        verbose,
+        stop: Arc::clone(&stop),
+        timings: timings.clone(),
+        thread_stats: thread_stats.clone(),
+        worker_cores,
+    };
+    let scorer = Arc::new(FrameScorer {
+        src_yuvcfg: src_config,
+        dst_yuvcfg: dst_config,
+        options: scoring,
+        display_size,
+        mask,
+        luma_scores,
+        highlight_weights,
+        dump: dump.clone(),
+    });
+    match (src_bd, dst_bd) {
+        (8, 8) => spawn_workers::<u8, u8>(video_compare, &workers, &scorer, overlay, &result_tx),
+        (8, _) => spawn_workers::<u8, u16>(video_compare, &workers, &scorer, overlay, &result_tx),
+        (_, 8) => spawn_workers::<u16, u8>(video_compare, &workers, &scorer, overlay, &result_tx),
+        (_, _) => spawn_workers::<u16, u16>(video_compare, &workers, &scorer, overlay, &result_tx),
+    }
+
     // Needs to be dropped or the main thread never stops waiting for scores
     drop(result_tx);
 
+    // How many frames of an input of this length are counted in the statistics
+    let frames_to_score_of = |frame_count: usize| {
+        let remaining = frame_count.saturating_sub(selection.skip_frames);
+        let selected = selection
+            .frames_to_compare
+            .unwrap_or(remaining)
+            .min((remaining as f64 / selection.inc as f64).ceil() as usize);
+        let excluded = selection
+            .exclude
+            .range(selection.skip_frames..)
+            .map(|frame| frame - selection.skip_frames)
+            .filter(|offset| offset % selection.inc == 0)
+            .filter(|offset| offset / selection.inc < selected)
+            .count();
+        (selected - excluded).saturating_sub(warmup)
+    };
+    let mut frames_to_score = frames_in_list
+        .map(|frames| frames.saturating_sub(warmup))
+        .or_else(|| {
+            source_frame_count
+                .or(distorted_frame_count)
+                .map(frames_to_score_of)
+        });
     let progress = if stderr().is_tty() && !verbose {
-        let frame_count = source_frame_count.or(distorted_frame_count);
-        let pb = if let Some(frame_count) = frame_count {
-            let fc = frames_to_compare.unwrap_or(frame_count - skip_frames)
-                .min(((frame_count - skip_frames) as f64 / inc as f64).ceil() as usize);
-
+        let pb = if let Some(fc) = frames_to_score {
             ProgressBar::new(fc as u64)
                 .with_style(pretty_progress_style())
                 .with_message(", avg: N/A")
@@ -481,54 +2457,471 @@ fn compare_videos_inner<D: Decoder + 'static, E: Decoder + 'static>(
         ProgressBar::hidden()
     };
 
+    // Results keep the actual scores, since `--stop-below` and `--dump-below` compare
+    // against those, and everything shown to the user goes through `shown`
+    let shown = |score: f64| if invert { 100.0 - score } else { score };
+    let label = if invert { "Distortion" } else { "Score" };
     let mut results = BTreeMap::new();
     let mut rolling_mean = 0f64;
-    for score in result_rx {
+    // Scores in the order they arrived, for `--avg-window`
+    let mut window = VecDeque::new();
+    let mut window_sum = 0f64;
+    // Verbose scores waiting to fill a line, for `--columns`
+    let mut row = Vec::new();
+    loop {
+        if let Some(frame_count) = lazy_frame_count.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            if frames_to_score.is_none() {
+                let total = frames_to_score_of(frame_count);
+                frames_to_score = Some(total);
+                progress.set_style(pretty_progress_style());
+                progress.set_length(total as u64);
+            }
+        }
+        // Once interrupted, only the scores that already arrived are kept
+        let result = if interrupted() {
+            stop.store(true, Ordering::Relaxed);
+            match result_rx.try_recv() {
+                Ok(result) => result,
+                Err(_) => break,
+            }
+        } else {
+            match result_rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(result) => result,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        };
+        let score = match result {
+            Ok(score) => score,
+            Err(e) => {
+                progress.abandon();
+                return Err(e);
+            }
+        };
+        if warmup_frames.remove(&score.0) {
+            if let Some(luma_scores) = &scorer.luma_scores {
+                luma_scores.lock().unwrap().remove(&score.0);
+            }
+            if let Some(highlight_weights) = &scorer.highlight_weights {
+                highlight_weights.lock().unwrap().remove(&score.0);
+            }
+            if warmup_frames.is_empty() {
+                // Frames that finished before the last warmup frame still count
+                progress.reset();
+                progress.set_position(results.len() as u64);
+                if let Some(timings) = &timings {
+                    timings.reset();
+                }
+                for stats in thread_stats.iter().flat_map(|stats| stats.iter()) {
+                    stats.reset();
+                }
+            }
+            continue;
+        }
         if verbose {
-            println!("Frame {}: {:.8}", score.0, score.1);
+            match columns {
+                Some(columns) => {
+                    row.push(format!("Frame {:>6}: {:.8}", score.0, shown(score.1)));
+                    if row.len() >= columns {
+                        report!("{}", row.join("   "));
+                        row.clear();
+                    }
+                }
+                None => report!("Frame {}: {:.8}", score.0, shown(score.1)),
+            }
+        }
+        if per_frame_stdout {
+            println!("{} {:.8}", score.0, shown(score.1));
+        }
+        if let Some(jsonl) = &mut jsonl {
+            let line = json!({
+                "schema_version": SCHEMA_VERSION,
+                "frame": score.0,
+                value_key(invert): shown(score.1),
+            });
+            if let Err(e) = writeln!(jsonl, "{line}") {
+                progress.abandon();
+                return Err(e.into());
+            }
+        }
+
+        if stop_below.is_some_and(|cutoff| score.1 < cutoff) {
+            stop.store(true, Ordering::Relaxed);
         }
 
         results.insert(score.0, score.1);
         rolling_mean = rolling_mean + (score.1 - rolling_mean) / (results.len() as f64);
-        progress.set_message(format!(", mean: {rolling_mean:.2}"));
+        let mut message = if let Some(avg_window) = avg_window {
+            window.push_back(score.1);
+            window_sum += score.1;
+            if window.len() > avg_window.max(1) {
+                window_sum -= window.pop_front().unwrap();
+            }
+            format!(", avg: {:.2}", shown(window_sum / window.len() as f64))
+        } else {
+            format!(", mean: {:.2}", shown(rolling_mean))
+        };
+        if show_current {
+            message.push_str(&format!(", cur: {:.2}", shown(score.1)));
+        }
+        progress.set_message(message);
         progress.inc(1);
     }
 
     progress.finish();
+    if !row.is_empty() {
+        report!("{}", row.join("   "));
+    }
+    if let Some(jsonl) = jsonl {
+        jsonl.finish()?;
+    }
+
+    // Frames are scored out of order, but every frame before the one that triggered the stop
+    // has already been decoded, so by now the earliest offender is guaranteed to be known
+    if let Some(cutoff) = stop_below {
+        if let Some((frame, score)) = results.iter().find(|(_, &score)| score < cutoff) {
+            report!("Stopped at frame {frame}: score {score:.8} is below {cutoff}");
+            report!();
+        }
+    }
+
+    if selection.iframes_only {
+        report!("Scored {} I frame(s)", results.len());
+        report!();
+    }
+
+    let excluded = excluded.load(Ordering::Relaxed);
+    if excluded > 0 {
+        report!("Excluded {excluded} frame(s) from scoring");
+        report!();
+    }
+
+    if interrupted() {
+        match frames_to_score {
+            Some(total) => report!(
+                "Interrupted: scored {} of {total} frames ({:.1}%)",
+                results.len(),
+                results.len() as f64 / total as f64 * 100.0
+            ),
+            None => report!("Interrupted: scored {} frames", results.len()),
+        }
+        report!();
+    }
+
+    if time_limit_reached.load(Ordering::Relaxed) {
+        match frames_to_score {
+            Some(total) => report!(
+                "Time limit reached: scored {} of {total} frames ({:.1}%)",
+                results.len(),
+                results.len() as f64 / total as f64 * 100.0
+            ),
+            None => report!("Time limit reached: scored {} frames", results.len()),
+        }
+        report!();
+    }
+
+    if !selection.timestamps.is_empty() {
+        for (timestamp, frame) in selection.timestamp_frames(frame_duration) {
+            let timestamp = format_timestamp(timestamp);
+            match results.get(&frame) {
+                Some(&score) => report!("{timestamp} (frame {frame}): {:.8}", shown(score)),
+                None => report!("{timestamp} (frame {frame}): past the end of the video"),
+            }
+        }
+        report!();
+    }
+
+    if results.is_empty() {
+        return Err(Ssimu2Error::NoFramesScored);
+    }
+    let out_of_range: Vec<f64> = results
+        .values()
+        .copied()
+        .filter(|score| !(0.0..=100.0).contains(score))
+        .collect();
+    if !out_of_range.is_empty() {
+        let (min, max) = out_of_range
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &score| {
+                (min.min(score), max.max(score))
+            });
+        warning!(
+            "{} frame score(s) are outside of 0 to 100 (from {min:.8} to {max:.8}); \
+             graphs extend their score axis to show them",
+            out_of_range.len()
+        );
+    }
 
-    let results: Vec<f64> = results.into_values().collect();
-    let frames = results.len();
+    let dumped_frames: Vec<usize> = dump.as_ref().map_or_else(Vec::new, |dump| {
+        results
+            .iter()
+            .filter(|(_, &score)| score < dump.below)
+            .map(|(&frame, _)| frame)
+            .collect()
+    });
+    let frame_scores: Vec<(usize, f64)> = results
+        .into_iter()
+        .map(|(frame, score)| (frame, shown(score)))
+        .collect();
+    let results: Vec<f64> = frame_scores.iter().map(|&(_, score)| score).collect();
     let mut data = Data::new(results.clone());
-    println!("Video Score for {} frames", frames);
-    println!("Mean: {:.8}", data.mean().unwrap());
-    println!("Median: {:.8}", data.median());
-    println!("Std Dev: {:.8}", data.std_dev().unwrap());
-    println!("5th Percentile: {:.8}", data.percentile(5));
-    println!("95th Percentile: {:.8}", data.percentile(95));
-
-    if graph {
-        use plotters::prelude::*;
-
-        let out_path = PathBuf::from(format!(
-            "ssimulacra2-video-{}.png",
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
-        ));
-        let width = 1500u32;
-        let height = 1000u32;
-        let mut image_buffer = vec![0; (width * height * 3) as usize].into_boxed_slice();
-
-        {
-            let root =
-                BitMapBackend::with_buffer(&mut image_buffer, (width, height)).into_drawing_area();
-            root.fill(&BLACK).unwrap();
-            let mut chart = ChartBuilder::on(&root)
-                .set_label_area_size(LabelAreaPosition::Left, 60)
-                .set_label_area_size(LabelAreaPosition::Bottom, 60)
-                .caption("SSIMULACRA2", ("sans-serif", 50.0))
-                .build_cartesian_2d(0..frames, 0f32..100f32)
+    let summary = Summary::new(&mut data, percentile_method);
+    if oneline {
+        summary.print_oneline();
+    } else if !table {
+        summary.print("Video", label);
+    }
+
+    if let Some(luma_scores) = &scorer.luma_scores {
+        let luma_scores: Vec<f64> = luma_scores
+            .lock()
+            .unwrap()
+            .values()
+            .map(|&score| shown(score))
+            .collect();
+        report!();
+        report!("Luma only (chroma differences removed):");
+        let luma_summary = Summary::new(&mut Data::new(luma_scores), percentile_method);
+        if oneline {
+            luma_summary.print_oneline();
+        } else {
+            luma_summary.print("Video", label);
+        }
+    }
+
+    if let Some(highlight_weights) = &scorer.highlight_weights {
+        let highlight_weights = highlight_weights.lock().unwrap();
+        report!();
+        report!(
+            "Highlight-weighted mean (HDR): {:.8}",
+            weighted_mean(&frame_scores, &highlight_weights)
+        );
+    }
+
+    if let Some(scenes) = &scenes {
+        report!();
+        print_scene_summary(&frame_scores, scenes);
+    }
+
+    if histogram {
+        print_histogram(&mut data);
+    }
+
+    if let Some(timings) = &timings {
+        report!();
+        timings.print(frame_threads);
+    }
+
+    if let Some(thread_stats) = &thread_stats {
+        report!();
+        ThreadStats::print(thread_stats);
+    }
+
+    // Every file written at the end of a run shares the time in its name
+    let run_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let output_path = |extension: &str| {
+        let file_name = format!("ssimulacra2-video-{run_time}.{extension}");
+        match &manifest {
+            Some(manifest) => manifest.dir().join(file_name),
+            None => PathBuf::from(file_name),
+        }
+    };
+
+    let graph_path = if graph || graph_output.is_some() || emit.contains(&Emit::Png) {
+        let out_path = graph_output.unwrap_or_else(|| output_path("png"));
+        // Nothing to move into place when the graph goes to stdout
+        let staged_path = if out_path == Path::new("-") {
+            out_path.clone()
+        } else {
+            outputs.stage(&out_path)
+        };
+        match graph_backend {
+            GraphBackend::Plotters => {
+                write_graph(&staged_path, &results, graph_size, graph_type, graph_color)?
+            }
+            GraphBackend::Pure => {
+                if graph_type != GraphType::Line {
+                    warning!(
+                        "The pure graph backend only draws line graphs, \
+                         ignoring --graph-type"
+                    );
+                }
+                write_pure_graph(&staged_path, &results, graph_size, graph_color)?
+            }
+        }
+        Some(out_path)
+    } else {
+        None
+    };
+
+    let csv_path = if emit.contains(&Emit::Csv) {
+        let out_path = output_path("csv");
+        write_csv(&outputs.stage(&out_path), &frame_scores, invert)?;
+        Some(out_path)
+    } else {
+        None
+    };
+    let json_path = if emit.contains(&Emit::Json) {
+        let out_path = output_path("json");
+        write_json(&outputs.stage(&out_path), &frame_scores, &summary, invert)?;
+        Some(out_path)
+    } else {
+        None
+    };
+
+    let manifest_path = if let Some(manifest) = &mut manifest {
+        manifest.set_config(json!({
+            "frame_threads": frame_threads,
+            "self_temporal": self_temporal,
+            "skip_frames": selection.skip_frames,
+            "frames": selection.frames_to_compare,
+            "increment": selection.inc,
+            "frame_duration": frame_duration,
+            "include_scene_changes": selection.include_scene_changes,
+            "random_sample": selection.random_sample,
+            "iframes_only": selection.iframes_only,
+            "seed": selection.seed,
+            "at": selection.timestamps.iter().map(|&ts| format_timestamp(ts)).collect::<Vec<_>>(),
+            "frame_list": selection.listed,
+            "exclude_frames": selection.exclude,
+            "scenes": scenes,
+            "plane_stats": plane_stats,
+            "hdr_stats": scorer.highlight_weights.is_some(),
+            "resize": resize,
+            "sar": sar.map(|(src, dst)| [format!("{}:{}", src.0, src.1), format!("{}:{}", dst.0, dst.1)]),
+            "downscale": downscale.map(|downscale| format!("{downscale:?}")),
+            "edges": edges,
+            "pixel_stride": pixel_stride,
+            "difference_space": format!("{difference_space:?}"),
+            "normalize_matrix": normalize_matrix.map(|primaries| format!("{primaries:?}")),
+            "mask": scorer.mask.is_some(),
+            "invert": invert,
+            "percentile_method": format!("{percentile_method:?}"),
+            "luma_window": luma_window.map(|window| [window.min, window.max]),
+            "stop_below": stop_below,
+            "time_limit": time_limit.map(|limit| limit.as_secs_f64()),
+            "warmup": warmup,
+            "dump_below": dump.as_ref().map(|dump| dump.below),
+            "source": yuv_config_json(&src_config, src_range_origin),
+            "distorted": yuv_config_json(&dst_config, dst_range_origin),
+        }));
+        if let Some(graph_path) = graph_path.as_ref().filter(|path| *path != Path::new("-")) {
+            manifest.add_file(graph_path.clone());
+        }
+        for path in csv_path.iter().chain(&json_path) {
+            manifest.add_file(path.clone());
+        }
+        if let Some(overlay_output) = &overlay_output {
+            manifest.add_file(overlay_output.clone());
+        }
+        if let Some(jsonl_path) = &jsonl_path {
+            manifest.add_file(jsonl_path.clone());
+        }
+        if let Some(dump) = &dump {
+            for frame in dumped_frames {
+                for file in dump.files(frame) {
+                    manifest.add_file(file);
+                }
+            }
+        }
+        Some(manifest.write(&mut outputs)?)
+    } else {
+        None
+    };
+
+    outputs.commit()?;
+    if let Some(graph_path) = graph_path {
+        report!();
+        if graph_path == Path::new("-") {
+            report!("Graph written to stdout");
+        } else {
+            report!("Graph written to {}", graph_path.to_string_lossy());
+        }
+    }
+    for path in csv_path.iter().chain(&json_path) {
+        report!();
+        report!("Scores written to {}", path.to_string_lossy());
+    }
+    if let Some(overlay_output) = overlay_output {
+        report!();
+        report!(
+            "Overlay video written to {}",
+            overlay_output.to_string_lossy()
+        );
+    }
+    if let Some(manifest_path) = manifest_path {
+        report!();
+        report!("Manifest written to {}", manifest_path.to_string_lossy());
+    }
+
+    Ok(summary)
+}
+
+/// Set by the first Ctrl-C
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes the first Ctrl-C stop decoding and summarize the frames scored so far, and a
+/// second one exit right away. The handler is installed once for the whole process.
+fn install_interrupt_handler() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        if let Err(e) = ctrlc::set_handler(|| {
+            if INTERRUPTED.swap(true, Ordering::Relaxed) {
+                std::process::exit(130);
+            }
+        }) {
+            warning!("Failed to install the Ctrl-C handler: {e}");
+        }
+    });
+}
+
+/// Whether the run was interrupted with Ctrl-C
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Describes how an input was interpreted, for the run manifest
+fn yuv_config_json(config: &YuvConfig, range_origin: RangeOrigin) -> serde_json::Value {
+    json!({
+        "bit_depth": config.bit_depth,
+        "subsampling_x": config.subsampling_x,
+        "subsampling_y": config.subsampling_y,
+        "full_range": config.full_range,
+        "range_origin": range_origin.as_str(),
+        "matrix": format!("{:?}", config.matrix_coefficients),
+        "transfer": format!("{:?}", config.transfer_characteristics),
+        "primaries": format!("{:?}", config.color_primaries),
+    })
+}
+
+pub fn write_graph(
+    path: &Path,
+    results: &[f64],
+    (width, height): (u32, u32),
+    graph_type: GraphType,
+    color: GraphColor,
+) -> io::Result<()> {
+    use plotters::prelude::*;
+
+    let mut image_buffer = vec![0; width as usize * height as usize * 3].into_boxed_slice();
+
+    {
+        let root =
+            BitMapBackend::with_buffer(&mut image_buffer, (width, height)).into_drawing_area();
+        root.fill(&BLACK).unwrap();
+        let (low, high) = score_range(results);
+        let mut chart_builder = ChartBuilder::on(&root);
+        chart_builder
+            .set_label_area_size(LabelAreaPosition::Left, 60)
+            .set_label_area_size(LabelAreaPosition::Bottom, 60)
+            .caption("SSIMULACRA2", ("sans-serif", 50.0));
+
+        if graph_type == GraphType::Line {
+            let mut chart = chart_builder
+                .build_cartesian_2d(0..results.len(), low as f32..high as f32)
                 .unwrap();
             chart
                 .configure_mesh()
@@ -544,32 +2937,337 @@ fn compare_videos_inner<D: Decoder + 'static, E: Decoder + 'static>(
             chart
                 .draw_series(
                     AreaSeries::new(
-                        results.into_iter().enumerate().map(|(i, v)| (i, v as f32)),
-                        0.0,
+                        results.iter().enumerate().map(|(i, &v)| (i, v as f32)),
+                        low as f32,
                         CYAN.mix(0.5),
                     )
                     .border_style(CYAN.filled()),
                 )
                 .unwrap();
-            root.present().expect("Unable to generate image");
+        } else {
+            // The distribution is drawn around the middle of an unlabeled x axis
+            let mut chart = chart_builder
+                .build_cartesian_2d(-1f64..1f64, low..high)
+                .unwrap();
+            chart
+                .configure_mesh()
+                .disable_x_mesh()
+                .disable_x_axis()
+                .bold_line_style(WHITE.mix(0.3))
+                .y_desc("Score")
+                .y_label_style(("sans-serif", 16, &WHITE))
+                .axis_desc_style(("sans-serif", 18, &WHITE))
+                .draw()
+                .unwrap();
+
+            let mut data = Data::new(results.to_vec());
+            if graph_type == GraphType::Box {
+                let (q1, median, q3) =
+                    (data.lower_quartile(), data.median(), data.upper_quartile());
+                let (min, max) = (data.min(), data.max());
+                chart
+                    .draw_series([
+                        Rectangle::new([(-0.3, q1), (0.3, q3)], CYAN.mix(0.5).filled()),
+                        Rectangle::new([(-0.3, q1), (0.3, q3)], CYAN.stroke_width(2)),
+                    ])
+                    .unwrap();
+                chart
+                    .draw_series(
+                        [
+                            vec![(-0.3, median), (0.3, median)],
+                            vec![(0.0, q3), (0.0, max)],
+                            vec![(0.0, q1), (0.0, min)],
+                            vec![(-0.15, max), (0.15, max)],
+                            vec![(-0.15, min), (0.15, min)],
+                        ]
+                        .into_iter()
+                        .map(|points| PathElement::new(points, CYAN.stroke_width(2))),
+                    )
+                    .unwrap();
+            } else {
+                let density = kernel_density(&mut data);
+                let peak = density.iter().map(|&(_, d)| d).fold(f64::EPSILON, f64::max);
+                let outline: Vec<(f64, f64)> = density
+                    .iter()
+                    .map(|&(score, d)| (0.8 * d / peak, score))
+                    .chain(
+                        density
+                            .iter()
+                            .rev()
+                            .map(|&(score, d)| (-0.8 * d / peak, score)),
+                    )
+                    .collect();
+                chart
+                    .draw_series([Polygon::new(outline.clone(), CYAN.mix(0.5).filled())])
+                    .unwrap();
+                chart
+                    .draw_series([PathElement::new(outline, CYAN.stroke_width(2))])
+                    .unwrap();
+            }
+        }
+        root.present().expect("Unable to generate image");
+    }
+
+    save_graph(path, &image_buffer, width, height, color)
+}
+
+/// Estimates the density of the scores with a Gaussian kernel, using Silverman's rule
+/// of thumb for the bandwidth. Returns (score, density) pairs spanning the data.
+fn kernel_density(data: &mut Data<Vec<f64>>) -> Vec<(f64, f64)> {
+    const POINTS: usize = 200;
+
+    let (min, max) = (data.min(), data.max());
+    let std_dev = data.std_dev().unwrap_or(0.0);
+    let iqr = data.upper_quartile() - data.lower_quartile();
+    let spread = if iqr > 0.0 {
+        std_dev.min(iqr / 1.34)
+    } else {
+        std_dev
+    };
+    // Identical scores have no spread, so they get a thin sliver instead of a spike
+    let bandwidth = (0.9 * spread * (data.len() as f64).powf(-0.2)).max(0.05);
+
+    let (start, end) = (min - 2.0 * bandwidth, max + 2.0 * bandwidth);
+    (0..POINTS)
+        .map(|i| {
+            let score = start + (end - start) * i as f64 / (POINTS - 1) as f64;
+            let density: f64 = data
+                .iter()
+                .map(|&x| (-0.5 * ((score - x) / bandwidth).powi(2)).exp())
+                .sum();
+            (score, density)
+        })
+        .collect()
+}
+
+fn print_histogram(data: &mut Data<Vec<f64>>) {
+    const BINS: usize = 20;
+    const BAR_WIDTH: usize = 50;
+
+    let min = data.order_statistic(1);
+    let max = data.order_statistic(data.len());
+    let bin_width = (max - min) / BINS as f64;
+
+    let mut bins = [0usize; BINS];
+    for &score in data.iter() {
+        // All scores land in the first bin if they are identical
+        let bin = if bin_width > 0.0 {
+            ((score - min) / bin_width) as usize
+        } else {
+            0
+        };
+        // The maximum value belongs to the last bin rather than a bin of its own
+        bins[bin.min(BINS - 1)] += 1;
+    }
+
+    let peak = bins.iter().copied().max().unwrap_or(0).max(1);
+    report!();
+    report!("Histogram:");
+    for (i, &count) in bins.iter().enumerate() {
+        let lower = min + bin_width * i as f64;
+        let upper = lower + bin_width;
+        report!(
+            "{lower:>7.2} - {upper:>7.2} | {:<width$} {count}",
+            "#".repeat(count * BAR_WIDTH / peak),
+            width = BAR_WIDTH
+        );
+    }
+}
+
+/// Whether an output path means "write to stdout"
+fn is_piped_output(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Parses a `[[HH:]MM:]SS[.mmm]` timestamp
+pub fn parse_timestamp(input: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid timestamp `{input}`, expected HH:MM:SS.mmm");
+
+    let mut parts = input.rsplit(':');
+    let seconds = parts
+        .next()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|s| s.is_finite() && *s >= 0.0)
+        .ok_or_else(invalid)?;
+    let mut total = seconds;
+    for multiplier in [60.0, 3600.0] {
+        if let Some(part) = parts.next() {
+            total += part.parse::<u32>().map_err(|_| invalid())? as f64 * multiplier;
+        }
+    }
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs_f64(total))
+}
+
+fn format_timestamp(timestamp: Duration) -> String {
+    let millis = timestamp.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Parses a set of frame indices from a file, or from the argument itself if no such
+/// file exists. Indices are separated by commas or whitespace, and `A-B` includes
+/// every frame from A to B.
+pub fn parse_frame_set(input: &str) -> Result<BTreeSet<usize>, String> {
+    let path = Path::new(input);
+    let list = if path.is_file() {
+        fs::read_to_string(path).map_err(|e| format!("failed to read {input}: {e}"))?
+    } else {
+        input.to_string()
+    };
+    parse_frame_items(&list)
+}
+
+/// Reads `--frame-list`, which is parsed like [`parse_frame_set`] but may also be `-` to
+/// read the list from stdin, as long as no input is piped in as well.
+pub fn read_frame_list(input: &str, stdin_taken: bool) -> Result<BTreeSet<usize>, Ssimu2Error> {
+    let invalid = |reason: String| Ssimu2Error::FrameList { reason };
+    if !is_piped(input) {
+        return parse_frame_set(input).map_err(invalid);
+    }
+    if stdin_taken {
+        return Err(invalid(
+            "cannot read it from stdin while an input is piped in".to_string(),
+        ));
+    }
+    let list = io::read_to_string(io::stdin())?;
+    parse_frame_items(&list).map_err(invalid)
+}
+
+fn parse_frame_items(list: &str) -> Result<BTreeSet<usize>, String> {
+    let mut frames = BTreeSet::new();
+    for item in list.split(|c: char| c == ',' || c.is_whitespace()) {
+        if item.is_empty() {
+            continue;
+        }
+        let invalid = || format!("invalid frame `{item}`, expected an index or START-END");
+        let parse = |value: &str| value.parse::<usize>().map_err(|_| invalid());
+        match item.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start > end {
+                    return Err(invalid());
+                }
+                frames.extend(start..=end);
+            }
+            None => {
+                frames.insert(parse(item)?);
+            }
         }
+    }
+    Ok(frames)
+}
+
+/// Parses a frame rate, either as a number or as a fraction like `24000/1001`
+pub fn parse_fps(input: &str) -> Result<f64, String> {
+    match input.split_once('/') {
+        Some((num, den)) => num
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .zip(den.trim().parse::<f64>().ok())
+            .map(|(num, den)| num / den),
+        None => input.trim().parse::<f64>().ok(),
+    }
+    .filter(|fps| fps.is_finite() && *fps > 0.0)
+    .ok_or_else(|| format!("invalid frame rate `{input}`, expected e.g. 24 or 24000/1001"))
+}
+
+/// Parses `START-END`, where both ends are timestamps as accepted by [`parse_timestamp`]
+pub fn parse_range(input: &str) -> Result<(Duration, Duration), String> {
+    let (start, end) = input
+        .split_once('-')
+        .ok_or_else(|| format!("invalid range `{input}`, expected START-END"))?;
+    let (start, end) = (parse_timestamp(start.trim())?, parse_timestamp(end.trim())?);
+    if start >= end {
+        return Err(format!(
+            "invalid range `{input}`, the start must be before the end"
+        ));
+    }
+    Ok((start, end))
+}
+
+pub fn parse_downscale(input: &str) -> Result<Downscale, String> {
+    if let Some((width, height)) = input.to_ascii_lowercase().split_once('x') {
+        let parse = |value: &str| value.trim().parse::<u32>().ok().filter(|&v| v > 0);
+        return match (parse(width), parse(height)) {
+            (Some(width), Some(height)) => Ok(Downscale::Size(width, height)),
+            _ => Err(format!("invalid size `{input}`, expected WIDTHxHEIGHT")),
+        };
+    }
+    input
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|factor| factor.is_finite() && *factor >= 1.0)
+        .map(Downscale::Factor)
+        .ok_or_else(|| {
+            format!("invalid downscale `{input}`, expected a factor of at least 1 or WIDTHxHEIGHT")
+        })
+}
+
+pub fn parse_luma_window(input: &str) -> Result<LumaWindow, String> {
+    let invalid = || format!("invalid luma window `{input}`, expected MIN-MAX between 0 and 1");
+    let (min, max) = input.split_once('-').ok_or_else(invalid)?;
+    let parse = |value: &str| {
+        value
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|value| (0.0..=1.0).contains(value))
+    };
+    match (parse(min), parse(max)) {
+        (Some(min), Some(max)) if min < max => Ok(LumaWindow { min, max }),
+        _ => Err(invalid()),
+    }
+}
 
-        image::save_buffer(&out_path, &image_buffer, width, height, ColorType::Rgb8)
-            .expect("Unable to save graph image");
+/// Smallest graph dimension that still leaves room for the axis labels
+const MIN_GRAPH_DIMENSION: u32 = 200;
+/// Largest graph dimension; keeps the RGB buffer allocation within a sane size
+const MAX_GRAPH_DIMENSION: u32 = 8192;
 
-        println!();
-        println!("Graph written to {}", out_path.to_string_lossy());
+pub fn parse_graph_size(input: &str) -> Result<(u32, u32), String> {
+    let (width, height) = input
+        .to_ascii_lowercase()
+        .split_once('x')
+        .and_then(|(width, height)| {
+            Some((
+                width.trim().parse::<u32>().ok()?,
+                height.trim().parse::<u32>().ok()?,
+            ))
+        })
+        .ok_or_else(|| format!("invalid graph size `{input}`, expected WIDTHxHEIGHT"))?;
+    let dimensions = MIN_GRAPH_DIMENSION..=MAX_GRAPH_DIMENSION;
+    if !dimensions.contains(&width) || !dimensions.contains(&height) {
+        return Err(format!(
+            "graph dimensions must be between {MIN_GRAPH_DIMENSION} and \
+             {MAX_GRAPH_DIMENSION} pixels"
+        ));
     }
+    Ok((width, height))
 }
 
-pub fn parse_matrix(input: &str) -> MatrixCoefficients {
+pub fn parse_matrix(input: &str) -> Result<MatrixCoefficients, Ssimu2Error> {
+    let color_parse_error = || Ssimu2Error::ColorParse {
+        kind: "matrix coefficients",
+        value: input.to_string(),
+    };
+
     if let Ok(intval) = input.parse::<u8>() {
         if intval <= MatrixCoefficients::ICtCp as u8 {
-            return MatrixCoefficients::from_u8(intval).expect("Invalid matrix coefficient value");
+            return MatrixCoefficients::from_u8(intval).ok_or_else(color_parse_error);
         }
     }
 
-    match input.to_ascii_lowercase().as_str() {
+    Ok(match input.to_ascii_lowercase().as_str() {
         "identity" | "rgb" | "srgb" | "smpte428" | "xyz" => MatrixCoefficients::Identity,
         "709" | "bt709" => MatrixCoefficients::BT709,
         "unspecified" => MatrixCoefficients::Unspecified,
@@ -590,19 +3288,23 @@ pub fn parse_matrix(input: &str) -> MatrixCoefficients {
         "cd-ncl" => MatrixCoefficients::ChromaticityDerivedNonConstantLuminance,
         "cd-cl" => MatrixCoefficients::ChromaticityDerivedConstantLuminance,
         "2100" | "bt2100" | "ictcp" => MatrixCoefficients::ICtCp,
-        _ => panic!("Unrecognized matrix coefficient string"),
-    }
+        _ => return Err(color_parse_error()),
+    })
 }
 
-pub fn parse_transfer(input: &str) -> TransferCharacteristic {
+pub fn parse_transfer(input: &str) -> Result<TransferCharacteristic, Ssimu2Error> {
+    let color_parse_error = || Ssimu2Error::ColorParse {
+        kind: "transfer characteristics",
+        value: input.to_string(),
+    };
+
     if let Ok(intval) = input.parse::<u8>() {
         if intval <= TransferCharacteristic::HybridLogGamma as u8 {
-            return TransferCharacteristic::from_u8(intval)
-                .expect("Invalid transfer characteristics value");
+            return TransferCharacteristic::from_u8(intval).ok_or_else(color_parse_error);
         }
     }
 
-    match input.to_ascii_lowercase().as_str() {
+    Ok(match input.to_ascii_lowercase().as_str() {
         "709" | "bt709" | "1886" | "bt1886" | "1361" | "bt1361" => TransferCharacteristic::BT1886,
         "unspecified" => TransferCharacteristic::Unspecified,
         "470m" | "bt470m" | "pal" => TransferCharacteristic::BT470M,
@@ -624,18 +3326,23 @@ pub fn parse_transfer(input: &str) -> TransferCharacteristic {
         }
         "428" | "smpte428" => TransferCharacteristic::ST428,
         "hlg" | "b67" | "arib-b67" => TransferCharacteristic::HybridLogGamma,
-        _ => panic!("Unrecognized transfer characteristics string"),
-    }
+        _ => return Err(color_parse_error()),
+    })
 }
 
-pub fn parse_primaries(input: &str) -> ColorPrimaries {
+pub fn parse_primaries(input: &str) -> Result<ColorPrimaries, Ssimu2Error> {
+    let color_parse_error = || Ssimu2Error::ColorParse {
+        kind: "color primaries",
+        value: input.to_string(),
+    };
+
     if let Ok(intval) = input.parse::<u8>() {
         if intval <= ColorPrimaries::Tech3213 as u8 {
-            return ColorPrimaries::from_u8(intval).expect("Invalid color primaries value");
+            return ColorPrimaries::from_u8(intval).ok_or_else(color_parse_error);
         }
     }
 
-    match input.to_ascii_lowercase().as_str() {
+    Ok(match input.to_ascii_lowercase().as_str() {
         "709" | "bt709" | "1361" | "bt1361" | "srgb" => ColorPrimaries::BT709,
         "unspecified" => ColorPrimaries::Unspecified,
         "470m" | "bt470m" => ColorPrimaries::BT470M,
@@ -650,8 +3357,23 @@ pub fn parse_primaries(input: &str) -> ColorPrimaries {
         "p3" | "p3dci" | "p3-dci" | "431" | "smpte431" => ColorPrimaries::P3DCI,
         "p3display" | "p3-display" | "432" | "smpte432" => ColorPrimaries::P3Display,
         "3213" | "tech3213" => ColorPrimaries::Tech3213,
-        _ => panic!("Unrecognized color primaries string"),
-    }
+        _ => return Err(color_parse_error()),
+    })
+}
+
+pub fn parse_subsampling(input: &str) -> Result<ChromaSampling, Ssimu2Error> {
+    Ok(match input.to_ascii_lowercase().as_str() {
+        "420" | "yuv420" => ChromaSampling::Cs420,
+        "422" | "yuv422" => ChromaSampling::Cs422,
+        "444" | "yuv444" => ChromaSampling::Cs444,
+        "400" | "gray" | "mono" => ChromaSampling::Cs400,
+        _ => {
+            return Err(Ssimu2Error::ColorParse {
+                kind: "chroma subsampling",
+                value: input.to_string(),
+            })
+        }
+    })
 }
 
 pub const fn guess_matrix_coefficients(width: usize, height: usize) -> MatrixCoefficients {
@@ -0,0 +1,82 @@
+//! Scores VapourSynth scripts through the binary. These need a working VapourSynth
+//! installation, so they are ignored by default; run them with `cargo test -- --ignored`.
+#![cfg(feature = "video")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A 10-bit clip with a different gradient in every plane
+const INTEGER_CLIP: &str = r#"
+import vapoursynth as vs
+
+core = vs.core
+clip = core.std.BlankClip(format=vs.YUV444P10, width=128, height=128, length=2)
+clip = core.std.Expr(clip, ["X 8 * Y 4 * + 1024 %", "X 8 * 1024 %", "Y 8 * 1024 %"])
+"#;
+
+/// Writes a script that outputs [`INTEGER_CLIP`] after the `filter` lines
+fn write_script(name: &str, filter: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "ssimulacra2_test_{}_{name}.vpy",
+        std::process::id()
+    ));
+    fs::write(
+        &path,
+        format!("{INTEGER_CLIP}{filter}\nclip.set_output()\n"),
+    )
+    .expect("Failed to write a script");
+    path
+}
+
+/// Mean score of `distorted` against `source`, both read as full range BT.709
+fn mean_score(source: &Path, distorted: &Path) -> f64 {
+    let output = Command::new(env!("CARGO_BIN_EXE_ssimulacra2_rs"))
+        .arg("video")
+        .arg(source)
+        .arg(distorted)
+        .args(["--src-range", "full", "--dst-range", "full"])
+        .args(["--src-matrix", "bt709", "--dst-matrix", "bt709"])
+        .args(["--src-transfer", "bt1886", "--dst-transfer", "bt1886"])
+        .args(["--src-primaries", "bt709", "--dst-primaries", "bt709"])
+        .output()
+        .expect("Failed to run ssimulacra2_rs");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "scoring failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Mean: "))
+        .and_then(|mean| mean.trim().parse().ok())
+        .unwrap_or_else(|| panic!("no mean score in the output: {stdout}"))
+}
+
+#[test]
+#[ignore = "needs VapourSynth"]
+fn float_clip_scores_like_its_integer_equivalent() {
+    let integer = write_script("integer", "");
+    // The same samples as 32-bit floats, with chroma centered on zero
+    let float = write_script(
+        "float",
+        r#"clip = core.std.Expr(clip, ["x 1023 /", "x 512 - 1023 /", "x 512 - 1023 /"], format=vs.YUV444PS)"#,
+    );
+    let distorted = write_script(
+        "distorted",
+        "clip = core.std.BoxBlur(clip, hradius=2, vradius=2)",
+    );
+
+    let integer_score = mean_score(&integer, &distorted);
+    let float_score = mean_score(&float, &distorted);
+    for path in [integer, float, distorted] {
+        let _ = fs::remove_file(path);
+    }
+
+    // Floats are read at 16 bits, which only differ from the 10-bit samples by rounding
+    assert!(
+        (integer_score - float_score).abs() < 1e-4,
+        "the float clip scored {float_score}, but its integer equivalent {integer_score}"
+    );
+}